@@ -0,0 +1,27 @@
+//! Print the events of a few processes as they arrive, by awaiting them on a
+//! stream rather than running a blocking director.
+
+use futures::StreamExt;
+use procman::ProcessManager;
+use std::process::Command;
+use std::time::Duration;
+
+fn main() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(10));
+    man.spawn("hello".to_string(), Command::new("echo").arg("hello"))
+        .expect("spawn failed");
+    man.spawn(
+        "count".to_string(),
+        Command::new("sh")
+            .arg("-c")
+            .arg("for i in 1 2 3; do echo $i; sleep 0.1; done"),
+    )
+    .expect("spawn failed");
+
+    futures::executor::block_on(async {
+        let mut stream = man.events_stream();
+        while let Some((name, ev)) = stream.next().await {
+            println!("{}: {}", name, ev);
+        }
+    });
+}
@@ -0,0 +1,172 @@
+//! An asynchronous counterpart to `run_process` for use within a tokio
+//! runtime, which awaits a process's output and exit instead of polling.
+
+use crate::{HandleType, ManagerError, ProcessError, ProcessEvent, ProcessManager};
+use std::io::Error;
+use std::os::unix::io::OwnedFd;
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+use tokio::net::unix::pipe;
+
+impl ProcessManager {
+    /// Start `command` under `name` and report its events like `run_process`,
+    /// but read its output and wait for it to exit asynchronously, so that no
+    /// thread sleeps between polls. Resolves once the process has exited. Of
+    /// the settings on `command`, only those of the underlying standard
+    /// library command take effect. If the process is detached, it is no
+    /// longer reported on, and this resolves once it exits regardless.
+    pub async fn spawn_async(
+        &self,
+        name: String,
+        mut command: tokio::process::Command,
+    ) -> Result<(), ManagerError> {
+        let ctl = self.start_process(name, command.as_std_mut())?;
+        let (queue, stdout, stderr, pid) = {
+            let mut ctl = ctl.write().unwrap();
+            (
+                ctl.event_queue.clone(),
+                ctl.child.stdout.take().map(OwnedFd::from),
+                ctl.child.stderr.take().map(OwnedFd::from),
+                ctl.child.id(),
+            )
+        };
+        #[cfg(feature = "tracing")]
+        let span = ctl.write().unwrap().span();
+
+        // Hold on to the control only while using it, so that `detach` can
+        // take the child.
+        let ctl = Arc::downgrade(&ctl);
+
+        let deliver = |ev: ProcessEvent| {
+            let ctl = match ctl.upgrade() {
+                Some(ctl) => ctl,
+                None => return,
+            };
+            let mut events = if self.config.strip_ansi {
+                ctl.write().unwrap().strip_ansi(ev)
+            } else {
+                vec![ev]
+            };
+            for ev in &mut events {
+                ctl.write().unwrap().history.record(ev);
+                if self.config.merged_output {
+                    if let ProcessEvent::Output(handle, _bytes) = ev {
+                        *handle = HandleType::StdOutput;
+                    }
+                }
+            }
+            if self.config.line_buffering {
+                events = events
+                    .into_iter()
+                    .flat_map(|ev| ctl.write().unwrap().split_lines(ev))
+                    .collect();
+            }
+            if let Some(encoding) = self.config.text_output {
+                events = events
+                    .into_iter()
+                    .flat_map(|ev| ctl.write().unwrap().decode_text(ev, encoding))
+                    .collect();
+            }
+            if self.config.name_prefix {
+                events = events
+                    .into_iter()
+                    .map(|ev| ctl.write().unwrap().prefix_name(ev))
+                    .collect();
+            }
+            for ev in events {
+                #[cfg(feature = "log")]
+                ctl.read().unwrap().log(&ev);
+                #[cfg(feature = "tracing")]
+                tracing::debug!(event = %ev, "process event");
+                queue.push(ev)
+            }
+        };
+        let deliver = &deliver;
+        let ctl = &ctl;
+
+        let read = |handle: HandleType, fd: Option<OwnedFd>| async move {
+            let mut receiver = match fd.map(pipe::Receiver::from_owned_fd) {
+                Some(Ok(receiver)) => receiver,
+                Some(Err(e)) => {
+                    return deliver(ProcessEvent::Error(ProcessError::ErrorReading(e)));
+                }
+                None => return,
+            };
+            let mut buf: Vec<u8> = vec![0; self.config.read_buffer_size];
+            loop {
+                match receiver.read(&mut buf).await {
+                    Ok(0) => return deliver(ProcessEvent::Eof(handle)),
+                    Ok(len) => {
+                        if let Some(ctl) = ctl.upgrade() {
+                            ctl.write().unwrap().count_read(handle, len);
+                        }
+                        deliver(ProcessEvent::Output(handle, buf[..len].to_vec()))
+                    }
+                    Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                    Err(e) => {
+                        return deliver(ProcessEvent::Error(ProcessError::ErrorReading(e)));
+                    }
+                }
+            }
+        };
+
+        let wait = async {
+            loop {
+                // Wait for the exit without reaping the child, so that its
+                // lock is not held meanwhile and `try_wait` can then collect
+                // the status. If someone else reaps it first, such as
+                // `stop_process`, `try_wait` returns the status they saw.
+                tokio::task::spawn_blocking(move || loop {
+                    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+                    let flags = libc::WEXITED | libc::WNOWAIT;
+                    if unsafe { libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, flags) }
+                        == 0
+                        || Error::last_os_error().raw_os_error() != Some(libc::EINTR)
+                    {
+                        return;
+                    }
+                })
+                .await
+                .map_err(Error::other)?;
+
+                match ctl.upgrade() {
+                    Some(ctl) => {
+                        if let Some(status) = ctl.write().unwrap().child.try_wait()? {
+                            return Ok(Some(status));
+                        }
+                    }
+                    None => return Ok(None),
+                }
+            }
+        };
+
+        let joined = async {
+            futures::join!(
+                read(HandleType::StdOutput, stdout),
+                read(HandleType::StdError, stderr),
+                wait
+            )
+        };
+        #[cfg(feature = "tracing")]
+        let joined = tracing::Instrument::instrument(joined, span.clone());
+        let ((), (), status) = joined.await;
+
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+        let events = match status {
+            Ok(Some(status)) => ProcessEvent::exit_events(status),
+            Ok(None) => return Ok(()),
+            Err(e) => vec![ProcessEvent::Error(ProcessError::ErrorWaiting(e))],
+        };
+        for ev in events {
+            #[cfg(feature = "log")]
+            if let Some(ctl) = ctl.upgrade() {
+                ctl.read().unwrap().log(&ev);
+            }
+            #[cfg(feature = "tracing")]
+            tracing::debug!(event = %ev, "process event");
+            queue.push(ev);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+//! Windows job objects, which stand in for process groups there: a child put
+//! in a job of its own takes every process it starts along into the job, and
+//! terminating the job ends them all.
+
+use std::ffi::c_void;
+use std::io::{Error, Result};
+use std::os::windows::io::AsRawHandle;
+use std::process::Child;
+use std::ptr;
+
+type Handle = *mut c_void;
+
+const JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE: u32 = 0x2000;
+const JOB_OBJECT_EXTENDED_LIMIT_INFORMATION: i32 = 9;
+
+#[repr(C)]
+#[derive(Default)]
+struct IoCounters {
+    read_operation_count: u64,
+    write_operation_count: u64,
+    other_operation_count: u64,
+    read_transfer_count: u64,
+    write_transfer_count: u64,
+    other_transfer_count: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct BasicLimitInformation {
+    per_process_user_time_limit: i64,
+    per_job_user_time_limit: i64,
+    limit_flags: u32,
+    minimum_working_set_size: usize,
+    maximum_working_set_size: usize,
+    active_process_limit: u32,
+    affinity: usize,
+    priority_class: u32,
+    scheduling_class: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct ExtendedLimitInformation {
+    basic_limit_information: BasicLimitInformation,
+    io_info: IoCounters,
+    process_memory_limit: usize,
+    job_memory_limit: usize,
+    peak_process_memory_used: usize,
+    peak_job_memory_used: usize,
+}
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn CreateJobObjectW(attributes: *mut c_void, name: *const u16) -> Handle;
+    fn SetInformationJobObject(job: Handle, class: i32, info: *mut c_void, len: u32) -> i32;
+    fn AssignProcessToJobObject(job: Handle, process: Handle) -> i32;
+    fn TerminateJobObject(job: Handle, exit_code: u32) -> i32;
+    fn CloseHandle(handle: Handle) -> i32;
+}
+
+/// A job holding one child and its descendants. Whatever is still running
+/// in it is killed once the job is dropped.
+pub(crate) struct Job(Handle);
+
+// The handle of a job can be used from any thread.
+unsafe impl Send for Job {}
+unsafe impl Sync for Job {}
+
+impl Job {
+    /// Put `child` in a new job of its own. Processes it already started
+    /// before then are not included.
+    pub(crate) fn for_child(child: &Child) -> Result<Job> {
+        let handle = unsafe { CreateJobObjectW(ptr::null_mut(), ptr::null()) };
+        if handle.is_null() {
+            return Err(Error::last_os_error());
+        }
+        let job = Job(handle);
+
+        let mut info = ExtendedLimitInformation::default();
+        info.basic_limit_information.limit_flags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+        if unsafe {
+            SetInformationJobObject(
+                job.0,
+                JOB_OBJECT_EXTENDED_LIMIT_INFORMATION,
+                &mut info as *mut ExtendedLimitInformation as *mut c_void,
+                std::mem::size_of::<ExtendedLimitInformation>() as u32,
+            )
+        } == 0
+        {
+            return Err(Error::last_os_error());
+        }
+        if unsafe { AssignProcessToJobObject(job.0, child.as_raw_handle() as Handle) } == 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(job)
+    }
+
+    /// Terminate every process in the job, as `Child::kill` would one.
+    pub(crate) fn terminate(&self) -> Result<()> {
+        if unsafe { TerminateJobObject(self.0, 1) } == 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        unsafe { CloseHandle(self.0) };
+    }
+}
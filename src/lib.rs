@@ -1,247 +1,3961 @@
-#![feature(await_macro, async_await)]
+#[cfg(all(unix, feature = "tokio"))]
+mod async_process;
+#[cfg(windows)]
+mod job;
+#[cfg(feature = "serde")]
+mod serialize;
 
-use std::collections::HashMap;
-use std::collections::VecDeque;
+use crossbeam_channel::{Receiver, SendTimeoutError, Sender, TrySendError};
+use futures::Stream;
+use regex::Regex;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::error;
+use std::ffi::OsString;
 use std::fmt;
-use std::io::Read;
+use std::fs;
+use std::io;
 use std::io::{Error, ErrorKind, Result};
+use std::io::{Read, Write};
+use std::ops;
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+#[cfg(unix)]
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
 use std::process::{Child, Command, ExitStatus, Stdio};
-use std::str;
 use std::string::String;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::sync::RwLock;
+use std::sync::{Condvar, Mutex};
 use std::thread;
 use std::time;
 
 type ProcessTable = Arc<RwLock<HashMap<String, Arc<RwLock<ProcessControl>>>>>;
-type EventQueue = Arc<RwLock<VecDeque<ProcessEvent>>>;
+type Subscribers = Arc<RwLock<Vec<Sender<(String, ProcessEvent)>>>>;
+type ExitStatuses = Arc<RwLock<HashMap<String, ExitStatus>>>;
 
 /// A `ProcessManager` manages a family of processes, where notable events in
 /// the life of those processes get reported to a "directing actor".
 #[derive(Clone, Default)]
 pub struct ProcessManager {
     processes: ProcessTable,
+    config: Arc<ProcessManagerConfig>,
+    #[cfg_attr(not(unix), allow(dead_code))]
+    forwarding_signals: Arc<AtomicBool>,
+    subscribers: Subscribers,
+    event_memory: Arc<AtomicUsize>,
+    retired: ExitStatuses,
+    closed: CancelToken,
+    kill_on_drop: Option<Arc<KillOnDrop>>,
 }
 
-struct ProcessControl {
-    child: Child,
-    event_queue: EventQueue,
+/// Kills every managed process once the last clone of a manager made with
+/// `with_kill_on_drop` goes away. The clones used by monitoring threads lack
+/// one, so that they do not keep it alive.
+struct KillOnDrop {
+    processes: ProcessTable,
+    kill_timeout: Option<time::Duration>,
 }
 
-#[derive(Clone, Copy, Debug)]
-pub enum HandleType {
-    StdInput,
-    StdOutput,
-    StdError,
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        // Take the processes out of the table under a single lock, but do the
+        // killing after it has been released.
+        let processes: Vec<_> = self.processes.write().unwrap().drain().collect();
+        for (_name, ctl) in processes {
+            let mut ctl = ctl.write().unwrap();
+            ctl.event_queue.abandon();
+            ctl.kill(self.kill_timeout).unwrap_or_default();
+        }
+    }
 }
 
-#[derive(Debug)]
-pub enum ProcessError {
-    ErrorWaiting(Error),
-    ErrorReading(Error),
-    ErrorHandling(Error),
+/// The settings a `ProcessManager` applies to every process it runs. Each
+/// can also be changed with the corresponding `with_*` method.
+#[derive(Clone, Debug)]
+pub struct ProcessManagerConfig {
+    /// How long monitoring loops and the director sleep between polls.
+    pub poll_interval: time::Duration,
+    /// The most output read from a handle in one go.
+    pub read_buffer_size: usize,
+    /// Whether output is reported as `Line` events instead of `Output`.
+    pub line_buffering: bool,
+    /// The initial and maximum delay before a restart, if any.
+    pub restart_backoff: Option<(time::Duration, time::Duration)>,
+    /// How many events may be queued for each process, if limited.
+    pub event_capacity: Option<usize>,
+    /// What to do with new events when a queue is full, or the memory budget
+    /// is spent.
+    pub overflow_policy: OverflowPolicy,
+    /// How many bytes of output may be queued by all processes together, if
+    /// limited.
+    pub total_event_memory: Option<usize>,
+    /// Whether stdout and stderr are reported as a single stream.
+    pub merged_output: bool,
+    /// How long `run_process_until_ready` waits, if limited.
+    pub ready_timeout: Option<time::Duration>,
+    /// Whether each child leads its own process group on Unix.
+    pub process_groups: bool,
+    /// Environment variables set for every process that does not set them.
+    pub default_env: HashMap<String, String>,
+    /// The working directory of every process that does not choose one.
+    pub default_cwd: Option<PathBuf>,
+    /// How many of its most recent lines of output each process keeps.
+    pub output_history: usize,
+    /// The output handles that are piped and reported as events. The others
+    /// are discarded.
+    pub handles: HandleSet,
+    /// How long stopping a process waits for it to die, if limited.
+    pub kill_timeout: Option<time::Duration>,
+    /// Whether each poll reads a handle until it is empty, and reports all
+    /// that was read as a single `Output` event.
+    pub coalesce: bool,
+    /// Whether ANSI escape sequences are removed from output events.
+    pub strip_ansi: bool,
+    /// The most bytes of output per second read from each process, if
+    /// limited.
+    pub output_rate_limit: Option<u64>,
+    /// How long a process may go without output before it is killed, if
+    /// limited.
+    pub watchdog: Option<time::Duration>,
+    /// How output is decoded into `Text` events, if it is.
+    pub text_output: Option<Encoding>,
+    /// Whether `Line` and `Text` events begin with the name of their process.
+    pub name_prefix: bool,
+    /// A file that every process reads as its stdin instead of a pipe from
+    /// us, if any.
+    pub stdin_file: Option<PathBuf>,
+    /// Whether children inherit none of our environment, only the variables
+    /// set for them.
+    pub env_clear: bool,
+    /// A hook run in each child before its command is executed, if any.
+    pub pre_exec: Option<PreExecHook>,
+    /// Whether managed processes are killed when the manager is dropped.
+    pub kill_on_drop: bool,
+    /// A function called as the director retires each process, if any.
+    pub exit_hook: Option<ExitHook>,
+    /// Where polling loops get the time from, and how they wait.
+    pub clock: Arc<dyn Clock>,
 }
 
-impl fmt::Display for ProcessError {
+impl Default for ProcessManagerConfig {
+    fn default() -> Self {
+        ProcessManagerConfig {
+            poll_interval: time::Duration::from_millis(200),
+            read_buffer_size: DEFAULT_READ_BUFFER_SIZE,
+            line_buffering: false,
+            restart_backoff: None,
+            event_capacity: None,
+            overflow_policy: OverflowPolicy::Block,
+            total_event_memory: None,
+            merged_output: false,
+            ready_timeout: None,
+            process_groups: false,
+            default_env: HashMap::new(),
+            default_cwd: None,
+            output_history: 0,
+            handles: HandleSet::BOTH,
+            kill_timeout: None,
+            coalesce: false,
+            strip_ansi: false,
+            output_rate_limit: None,
+            watchdog: None,
+            text_output: None,
+            name_prefix: false,
+            stdin_file: None,
+            env_clear: false,
+            pre_exec: None,
+            exit_hook: None,
+            kill_on_drop: false,
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+/// A function run in every child after it is forked and before its command
+/// is executed, as set with `ProcessManager::with_pre_exec`.
+#[derive(Clone)]
+pub struct PreExecHook(
+    #[cfg_attr(not(unix), allow(dead_code))] Arc<dyn Fn() -> Result<()> + Send + Sync>,
+);
+
+impl PreExecHook {
+    /// # Safety
+    ///
+    /// `hook` runs in the child of a fork of a multithreaded process, so it
+    /// must only do what is async-signal-safe: no allocating, no locking, and
+    /// no touching state that another thread might have been changing. See
+    /// `std::os::unix::process::CommandExt::pre_exec`.
+    pub unsafe fn new<F>(hook: F) -> Self
+    where
+        F: Fn() -> Result<()> + Send + Sync + 'static,
+    {
+        PreExecHook(Arc::new(hook))
+    }
+}
+
+impl fmt::Debug for PreExecHook {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            ProcessError::ErrorWaiting(e) => write!(f, "ErrorWaiting: {}", e),
-            ProcessError::ErrorReading(e) => write!(f, "ErrorReading: {}", e),
-            ProcessError::ErrorHandling(e) => write!(f, "ErrorHandling: {}", e),
+        write!(f, "PreExecHook")
+    }
+}
+
+/// Where the polling loops of a manager get the current time from, and how
+/// they wait between polls, as set with `ProcessManager::with_clock`.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> time::Instant;
+    fn sleep(&self, duration: time::Duration);
+}
+
+/// The system's clock, on which sleeping takes real time. This is the
+/// default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> time::Instant {
+        time::Instant::now()
+    }
+
+    fn sleep(&self, duration: time::Duration) {
+        thread::sleep(duration)
+    }
+}
+
+/// A clock for tests, on which time only passes when it is advanced.
+/// Sleeping waits until it has been advanced past the end of the sleep, so
+/// that the test decides when each poll, timeout and backoff goes by. Clones
+/// share the same time.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    start: time::Instant,
+    elapsed: Arc<(Mutex<time::Duration>, Condvar)>,
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        MockClock {
+            start: time::Instant::now(),
+            elapsed: Default::default(),
         }
     }
+
+    /// Move the time on by `duration`, waking whoever has slept long enough.
+    pub fn advance(&self, duration: time::Duration) {
+        let (elapsed, advanced) = &*self.elapsed;
+        *elapsed.lock().unwrap() += duration;
+        advanced.notify_all();
+    }
 }
 
-impl error::Error for ProcessError {}
+impl Default for MockClock {
+    fn default() -> Self {
+        MockClock::new()
+    }
+}
 
-#[derive(Debug)]
-pub enum ManagerError {
-    ProcessUnknown,
+impl Clock for MockClock {
+    fn now(&self) -> time::Instant {
+        self.start + *self.elapsed.0.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: time::Duration) {
+        let (elapsed, advanced) = &*self.elapsed;
+        let mut now = elapsed.lock().unwrap();
+        let deadline = *now + duration;
+        while *now < deadline {
+            now = advanced.wait(now).unwrap();
+        }
+    }
 }
 
-const MAX_LINE: usize = 8192;
+/// A function called with the name and exit status of each process as the
+/// director retires it, as set with `ProcessManager::with_exit_hook`.
+#[derive(Clone)]
+pub struct ExitHook(Arc<ExitHookFn>);
 
-#[derive(Debug)]
-pub enum ProcessEvent {
-    Exited(ExitStatus),
-    Error(ProcessError),
-    Output(HandleType, Vec<u8>, usize),
+type ExitHookFn = dyn Fn(&str, ExitStatus) + Send + Sync;
+
+impl ExitHook {
+    pub fn new<F>(hook: F) -> Self
+    where
+        F: Fn(&str, ExitStatus) + Send + Sync + 'static,
+    {
+        ExitHook(Arc::new(hook))
+    }
 }
 
-impl fmt::Display for ProcessEvent {
+impl fmt::Debug for ExitHook {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ExitHook")
+    }
+}
+
+/// How output is decoded when it is reported as text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-8, with invalid sequences replaced by U+FFFD.
+    Utf8Lossy,
+    /// UTF-8, with output that is not valid reported as an error instead.
+    Utf8Strict,
+    /// ISO-8859-1, in which every byte is the character of the same value.
+    Latin1,
+    /// Any encoding that `encoding_rs` knows, such as Shift_JIS or
+    /// windows-1252, with malformed sequences replaced by U+FFFD.
+    #[cfg(feature = "encoding_rs")]
+    Named(&'static encoding_rs::Encoding),
+}
+
+impl Encoding {
+    fn decode(self, bytes: &[u8]) -> Result<String> {
         match self {
-            ProcessEvent::Exited(status) => write!(f, "Exited({})", status),
-            ProcessEvent::Error(err) => write!(f, "Error({})", err),
-            ProcessEvent::Output(handle, bytes, len) => write!(
-                f,
-                "Output({:?}, {:?}, {})",
-                handle,
-                str::from_utf8(&bytes[0..*len]),
-                len
-            ),
+            Encoding::Utf8Lossy => Ok(String::from_utf8_lossy(bytes).into_owned()),
+            Encoding::Utf8Strict => {
+                String::from_utf8(bytes.to_vec()).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+            }
+            Encoding::Latin1 => Ok(bytes.iter().map(|&b| b as char).collect()),
+            #[cfg(feature = "encoding_rs")]
+            Encoding::Named(encoding) => {
+                Ok(encoding.decode_without_bom_handling(bytes).0.into_owned())
+            }
         }
     }
 }
 
-impl ProcessManager {
-    pub fn new() -> Self {
-        Default::default()
+/// Where a process's stdout or stderr goes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum OutputTarget {
+    /// Read by the monitoring loop and reported as events.
+    #[default]
+    Events,
+    /// Appended to the file at this path, which is created if need be,
+    /// without passing through the manager at all.
+    File(PathBuf),
+    /// Reported as events, and also appended to the file at this path.
+    Tee(PathBuf),
+}
+
+impl OutputTarget {
+    fn open(path: &PathBuf) -> Result<fs::File> {
+        fs::OpenOptions::new().create(true).append(true).open(path)
     }
 
-    pub fn run_director_with_intercept<F>(&self, on_event: F) -> Result<()>
-    where
-        F: Fn(ProcessEvent, &mut dyn FnMut(ProcessEvent)),
-    {
-        loop {
-            thread::sleep(time::Duration::from_millis(200));
+    /// The `Stdio` for a handle sent here, where a handle sent to events is
+    /// discarded instead unless it is `selected`.
+    fn stdio(&self, selected: bool) -> Result<Stdio> {
+        Ok(match self {
+            OutputTarget::Events if !selected => Stdio::null(),
+            OutputTarget::Events | OutputTarget::Tee(_) => Stdio::piped(),
+            OutputTarget::File(path) => OutputTarget::open(path)?.into(),
+        })
+    }
+
+    fn tee(&self) -> Result<Option<io::BufWriter<fs::File>>> {
+        Ok(match self {
+            OutputTarget::Tee(path) => Some(io::BufWriter::new(OutputTarget::open(path)?)),
+            _ => None,
+        })
+    }
+}
 
-            let mut to_remove: Vec<String> = Vec::new();
+/// What a process's monitoring loop does with a new event when its queue is
+/// already at capacity.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OverflowPolicy {
+    /// Wait for the director to make room.
+    #[default]
+    Block,
+    /// Discard the oldest queued event to make room for the new one.
+    DropOldest,
+    /// Discard the new event.
+    DropNewest,
+}
 
-            if self.processes.read().unwrap().len() == 0 {
-                return Ok(());
-            } else {
-                for (name, ctl) in self.processes.write().unwrap().iter_mut() {
-                    if let Some(ev) = (*ctl)
-                        .write()
-                        .unwrap()
-                        .event_queue
-                        .write()
-                        .unwrap()
-                        .pop_front()
-                    {
-                        on_event(ev, &mut |ev| {
-                            if let ProcessEvent::Exited(_code) = ev {
-                                to_remove.push(name.to_string())
-                            }
-                        })
+/// The events produced by one process that are waiting for the director. If
+/// it was given a capacity, what happens when it is full is decided by its
+/// overflow policy. Discarded events are reported with a single `Dropped`
+/// the next time the director takes from the queue. Once the process has
+/// been taken out of the table the queue is abandoned, as nothing will take
+/// from it again, and events pushed after that are discarded.
+#[derive(Clone)]
+struct EventQueue {
+    sender: Sender<TimedEvent>,
+    receiver: Receiver<TimedEvent>,
+    policy: OverflowPolicy,
+    dropped: Arc<AtomicUsize>,
+    memory: Option<Arc<QueueMemory>>,
+    abandoned: Arc<AtomicBool>,
+    clock: Arc<dyn Clock>,
+}
+
+/// The bytes of output held in one queue, which count against a budget that
+/// all the queues of a manager share. Whatever is still held when the queue
+/// goes away is given back.
+struct QueueMemory {
+    limit: usize,
+    total: Arc<AtomicUsize>,
+    held: AtomicUsize,
+}
+
+impl QueueMemory {
+    /// Count `size` more bytes against the budget, if they fit. An event
+    /// larger than the whole budget fits once nothing else is held.
+    fn reserve(&self, size: usize) -> bool {
+        let reserved = self
+            .total
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |total| {
+                (size == 0 || total == 0 || total + size <= self.limit).then_some(total + size)
+            })
+            .is_ok();
+        if reserved {
+            self.held.fetch_add(size, Ordering::SeqCst);
+        }
+        reserved
+    }
+
+    fn release(&self, size: usize) {
+        self.held.fetch_sub(size, Ordering::SeqCst);
+        self.total.fetch_sub(size, Ordering::SeqCst);
+    }
+}
+
+impl Drop for QueueMemory {
+    fn drop(&mut self) {
+        self.total.fetch_sub(*self.held.get_mut(), Ordering::SeqCst);
+    }
+}
+
+/// The bytes of output an event holds while it is queued.
+fn event_size(ev: &ProcessEvent) -> usize {
+    match ev {
+        ProcessEvent::Output(_handle, bytes) => bytes.len(),
+        ProcessEvent::Line(_handle, line) => line.len(),
+        ProcessEvent::Text(_handle, text) => text.len(),
+        _ => 0,
+    }
+}
+
+impl EventQueue {
+    fn new(
+        capacity: Option<usize>,
+        policy: OverflowPolicy,
+        memory: Option<(usize, Arc<AtomicUsize>)>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let (sender, receiver) = match capacity {
+            Some(capacity) => crossbeam_channel::bounded(capacity),
+            None => crossbeam_channel::unbounded(),
+        };
+        EventQueue {
+            sender,
+            receiver,
+            policy,
+            dropped: Default::default(),
+            memory: memory.map(|(limit, total)| {
+                Arc::new(QueueMemory {
+                    limit,
+                    total,
+                    held: AtomicUsize::new(0),
+                })
+            }),
+            abandoned: Default::default(),
+            clock,
+        }
+    }
+
+    fn push(&self, ev: ProcessEvent) {
+        // The director relies on seeing `Exited` to retire a process, so that
+        // is never discarded. Sending can only fail once every receiver is
+        // gone, and we hold one.
+        let policy = match ev {
+            ProcessEvent::Exited(_) => OverflowPolicy::Block,
+            _ => self.policy,
+        };
+        if !self.reserve(event_size(&ev), policy) {
+            self.dropped.fetch_add(1, Ordering::SeqCst);
+            return;
+        }
+        let ev = TimedEvent::now(ev);
+        match policy {
+            OverflowPolicy::Block => {
+                let mut ev = ev;
+                while let Err(SendTimeoutError::Timeout(rejected)) = self
+                    .sender
+                    .send_timeout(ev, time::Duration::from_millis(10))
+                {
+                    if self.is_abandoned() {
+                        self.release(&rejected.event);
+                        return;
                     }
+                    ev = rejected;
                 }
-
-                for name in to_remove {
-                    let mut procs = self.processes.write().unwrap();
-                    procs.remove(&name);
+            }
+            OverflowPolicy::DropOldest => {
+                let mut ev = ev;
+                while let Err(TrySendError::Full(rejected)) = self.sender.try_send(ev) {
+                    if let Ok(oldest) = self.receiver.try_recv() {
+                        self.release(&oldest.event);
+                        self.dropped.fetch_add(1, Ordering::SeqCst);
+                    }
+                    ev = rejected;
+                }
+            }
+            OverflowPolicy::DropNewest => {
+                if let Err(TrySendError::Full(rejected)) = self.sender.try_send(ev) {
+                    self.release(&rejected.event);
+                    self.dropped.fetch_add(1, Ordering::SeqCst);
                 }
             }
         }
     }
 
-    pub fn run_director(&self) -> Result<()> {
-        self.run_director_with_intercept(|ev, k: &mut dyn FnMut(ProcessEvent)| k(ev))
+    /// Count `size` bytes against the memory budget, if there is one, making
+    /// room as `policy` says when it is spent. Returns false if the event is
+    /// to be dropped instead.
+    fn reserve(&self, size: usize, policy: OverflowPolicy) -> bool {
+        let memory = match &self.memory {
+            Some(memory) => memory,
+            None => return true,
+        };
+        while !memory.reserve(size) {
+            match policy {
+                OverflowPolicy::Block if self.is_abandoned() => return false,
+                OverflowPolicy::Block => self.clock.sleep(time::Duration::from_millis(1)),
+                OverflowPolicy::DropOldest => match self.receiver.try_recv() {
+                    Ok(oldest) => {
+                        self.release(&oldest.event);
+                        self.dropped.fetch_add(1, Ordering::SeqCst);
+                    }
+                    Err(_) => return false,
+                },
+                OverflowPolicy::DropNewest => return false,
+            }
+        }
+        true
     }
 
-    pub fn run_process_with_intercept<F>(
-        &self,
-        name: String,
-        command: &mut Command,
-        on_event: F,
-    ) -> Result<()>
-    where
-        F: Fn(ProcessEvent, &dyn Fn(ProcessEvent) -> Result<()>) -> Result<()>,
-    {
-        // Remember some details about `config`, since we will be moving it.
-        let name: String = name.to_string();
+    fn release(&self, ev: &ProcessEvent) {
+        if let Some(memory) = &self.memory {
+            memory.release(event_size(ev));
+        }
+    }
 
-        // Spawn the child process, which begins running immediately.
-        let child = command
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
-        let mut ctl = ProcessControl {
-            child,
-            event_queue: Default::default(),
-        };
+    /// Stop waiting for room in the queue, because nothing will take from it
+    /// again.
+    fn abandon(&self) {
+        self.abandoned.store(true, Ordering::SeqCst);
+    }
 
-        // Record the command in our "process table", and if we cannot because
-        // of a name overlap, kill both the old and new processes and report
-        // the error.
-        let ctl = self
-            .processes
-            .write()
-            .unwrap()
-            .entry(name.to_string())
-            .and_modify(|e| {
-                (*e).write().unwrap().child.kill().unwrap_or_default();
-                ctl.child.kill().unwrap_or_default();
-                panic!("Overwriting existing process with name {}", name)
-            })
-            .or_insert_with(|| Arc::new(RwLock::new(ctl)))
-            .clone();
+    fn is_abandoned(&self) -> bool {
+        self.abandoned.load(Ordering::SeqCst)
+    }
 
-        let mut buf: [u8; MAX_LINE] = [0; MAX_LINE];
-        let on_event = |ctl: &ProcessControl, ev: ProcessEvent| -> Result<()> {
-            if let Err(e) = (on_event)(ev, &move |ev| {
-                ctl.event_queue.write().unwrap().push_back(ev);
-                Ok(())
-            }) {
-                ctl.event_queue
-                    .write()
-                    .unwrap()
-                    .push_back(ProcessEvent::Error(ProcessError::ErrorHandling(e)))
-            };
-            Ok(())
-        };
+    /// The number of events that `pop` would return right now.
+    fn len(&self) -> usize {
+        let dropped = self.dropped.load(Ordering::SeqCst) > 0;
+        self.receiver.len() + dropped as usize
+    }
 
-        loop {
-            thread::sleep(time::Duration::from_millis(200));
+    fn pop(&self) -> Option<TimedEvent> {
+        match self.dropped.swap(0, Ordering::SeqCst) {
+            0 => {
+                let ev = self.receiver.try_recv().ok()?;
+                self.release(&ev.event);
+                Some(ev)
+            }
+            count => Some(TimedEvent::now(ProcessEvent::Dropped(count))),
+        }
+    }
+}
 
-            let mut ctl = ctl.write().unwrap();
+/// Where the command to restart a process with comes from.
+enum Restart<'a> {
+    /// The command the process was started with.
+    Command(&'a mut Command),
+    /// A function building a new command for each attempt.
+    Factory(&'a dyn Fn(u32) -> Command),
+}
 
-            // Check whether this is output to be read.
-            if let Some(h) = &mut ctl.child.stdout {
-                match h.read(&mut buf) {
-                    Ok(len) => (on_event)(
-                        &ctl,
-                        ProcessEvent::Output(HandleType::StdOutput, buf.to_vec(), len),
-                    ),
-                    Err(e) => (on_event)(&ctl, ProcessEvent::Error(ProcessError::ErrorReading(e))),
-                }
-            } else {
-                Ok(())
-            }?;
+type RestartPredicate<'a> = dyn Fn(ExitStatus, u32) -> bool + 'a;
 
-            if let Some(h) = &mut ctl.child.stderr {
-                match h.read(&mut buf) {
-                    Ok(len) => (on_event)(
-                        &ctl,
-                        ProcessEvent::Output(HandleType::StdError, buf.to_vec(), len),
-                    ),
-                    Err(e) => (on_event)(&ctl, ProcessEvent::Error(ProcessError::ErrorReading(e))),
-                }
-            } else {
-                Ok(())
-            }?;
+/// Settings for monitoring one particular process.
+#[derive(Default)]
+struct MonitorOptions<'a> {
+    /// Spawn the command again after an exit, whenever the predicate, given
+    /// the exit status and the number of restarts so far, permits it.
+    restart: Option<(Restart<'a>, &'a RestartPredicate<'a>)>,
+    /// Kill each run of the process that is still going after this long.
+    timeout: Option<time::Duration>,
+}
 
-            let result: Result<()> = match ctl.child.try_wait() {
-                Ok(None) => Ok(()),
-                Ok(Some(status)) => return (on_event)(&ctl, ProcessEvent::Exited(status)),
-                Err(e) => {
-                    return (on_event)(&ctl, ProcessEvent::Error(ProcessError::ErrorWaiting(e)))
+struct ProcessControl {
+    name: String,
+    child: Child,
+    event_queue: EventQueue,
+    stdout_line: Vec<u8>,
+    stderr_line: Vec<u8>,
+    monitor: Option<thread::JoinHandle<std::result::Result<(), ManagerError>>>,
+    tags: HashSet<String>,
+    paused: bool,
+    detached: bool,
+    process_group: bool,
+    #[cfg(windows)]
+    job: Option<job::Job>,
+    stdout_tee: Option<io::BufWriter<fs::File>>,
+    stderr_tee: Option<io::BufWriter<fs::File>>,
+    history: OutputHistory,
+    stdin_buffer: VecDeque<u8>,
+    closing_stdin: bool,
+    stdout_escape: Vec<u8>,
+    stderr_escape: Vec<u8>,
+    stdout_text: Vec<u8>,
+    stderr_text: Vec<u8>,
+    stdout_mid_line: bool,
+    stderr_mid_line: bool,
+    #[cfg(feature = "encoding_rs")]
+    stdout_decoder: Option<encoding_rs::Decoder>,
+    #[cfg(feature = "encoding_rs")]
+    stderr_decoder: Option<encoding_rs::Decoder>,
+    started: time::Instant,
+    command_line: Vec<String>,
+    stdout_read: u64,
+    stderr_read: u64,
+    rate_limiter: Option<RateLimiter>,
+    clock: Arc<dyn Clock>,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+/// How many more bytes of output may be read from a process, replenished at
+/// a fixed rate up to one second's worth. Output beyond that waits in the
+/// pipe, so that the child is slowed down rather than its output lost.
+struct RateLimiter {
+    rate: u64,
+    allowance: u64,
+    refilled: time::Instant,
+    throttled: bool,
+}
+
+impl RateLimiter {
+    fn new(rate: u64, now: time::Instant) -> Self {
+        RateLimiter {
+            rate,
+            allowance: rate,
+            refilled: now,
+            throttled: false,
+        }
+    }
+
+    fn refill(&mut self, now: time::Instant) {
+        let elapsed = now.duration_since(self.refilled);
+        let earned = (elapsed.as_secs_f64() * self.rate as f64) as u64;
+        // Keep the time towards the next whole byte when none was earned.
+        if earned > 0 {
+            self.allowance = std::cmp::min(self.allowance.saturating_add(earned), self.rate);
+            self.refilled = now;
+        }
+    }
+}
+
+/// The most recent lines of output of a process, up to a fixed number, with
+/// the oldest evicted first.
+struct OutputHistory {
+    capacity: usize,
+    lines: VecDeque<String>,
+    stdout_partial: Vec<u8>,
+    stderr_partial: Vec<u8>,
+}
+
+impl OutputHistory {
+    fn new(capacity: usize) -> Self {
+        OutputHistory {
+            capacity,
+            lines: VecDeque::with_capacity(capacity),
+            stdout_partial: Vec::new(),
+            stderr_partial: Vec::new(),
+        }
+    }
+
+    /// Add the lines completed by `ev`. A partial line is held back until
+    /// the rest of it arrives or its handle reaches end-of-file.
+    fn record(&mut self, ev: &ProcessEvent) {
+        if self.capacity == 0 {
+            return;
+        }
+        match ev {
+            ProcessEvent::Output(handle, bytes) => {
+                let partial = match self.partial(*handle) {
+                    Some(partial) => partial,
+                    None => return,
+                };
+                partial.extend_from_slice(bytes);
+                let mut lines = Vec::new();
+                while let Some(pos) = partial.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = partial.drain(..=pos).collect();
+                    lines.push(String::from_utf8_lossy(&line[..pos]).into_owned());
                 }
-            };
+                lines.into_iter().for_each(|line| self.push(line));
+            }
+            ProcessEvent::Eof(handle) => {
+                if let Some(partial) = self.partial(*handle) {
+                    if !partial.is_empty() {
+                        let line = String::from_utf8_lossy(&std::mem::take(partial)).into_owned();
+                        self.push(line);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
 
-            result?
+    fn partial(&mut self, handle: HandleType) -> Option<&mut Vec<u8>> {
+        match handle {
+            HandleType::StdInput => None,
+            HandleType::StdOutput => Some(&mut self.stdout_partial),
+            HandleType::StdError => Some(&mut self.stderr_partial),
         }
     }
 
-    pub fn run_process(&self, name: String, command: &mut Command) -> Result<()> {
-        self.run_process_with_intercept(
-            name,
-            command,
-            |ev, k: &dyn Fn(ProcessEvent) -> Result<()>| k(ev),
-        )
+    fn push(&mut self, line: String) {
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
     }
+}
 
-    pub fn stop_process(&mut self, name: &str) -> Result<()> {
-        if let Some(v) = self.processes.write().unwrap().remove(name) {
-            v.write().unwrap().child.kill()?;
-            Ok(())
-        } else {
-            Err(Error::new(
-                ErrorKind::Other,
-                format!("Could not find entry {} to be stopped", name),
-            ))
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum HandleType {
+    StdInput,
+    StdOutput,
+    StdError,
+}
+
+/// A selection of the output handles of a process, combined with `|`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HandleSet {
+    stdout: bool,
+    stderr: bool,
+}
+
+impl HandleSet {
+    pub const NONE: HandleSet = HandleSet {
+        stdout: false,
+        stderr: false,
+    };
+    pub const STDOUT: HandleSet = HandleSet {
+        stdout: true,
+        stderr: false,
+    };
+    pub const STDERR: HandleSet = HandleSet {
+        stdout: false,
+        stderr: true,
+    };
+    pub const BOTH: HandleSet = HandleSet {
+        stdout: true,
+        stderr: true,
+    };
+
+    pub fn contains(self, handle: HandleType) -> bool {
+        match handle {
+            HandleType::StdInput => false,
+            HandleType::StdOutput => self.stdout,
+            HandleType::StdError => self.stderr,
+        }
+    }
+}
+
+impl Default for HandleSet {
+    fn default() -> Self {
+        HandleSet::BOTH
+    }
+}
+
+impl ops::BitOr for HandleSet {
+    type Output = HandleSet;
+
+    fn bitor(self, other: HandleSet) -> HandleSet {
+        HandleSet {
+            stdout: self.stdout || other.stdout,
+            stderr: self.stderr || other.stderr,
+        }
+    }
+}
+
+impl fmt::Display for HandleType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandleType::StdInput => write!(f, "stdin"),
+            HandleType::StdOutput => write!(f, "stdout"),
+            HandleType::StdError => write!(f, "stderr"),
+        }
+    }
+}
+
+/// A failure met while monitoring a process. It does not name the process;
+/// the director passes the name alongside every event, as do `subscribe` and
+/// `try_recv_event`, so that simultaneous failures can be told apart.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ProcessError {
+    ErrorWaiting(#[cfg_attr(feature = "serde", serde(with = "serialize::io_error"))] Error),
+    ErrorReading(#[cfg_attr(feature = "serde", serde(with = "serialize::io_error"))] Error),
+    ErrorHandling(#[cfg_attr(feature = "serde", serde(with = "serialize::io_error"))] Error),
+    ErrorRestarting(#[cfg_attr(feature = "serde", serde(with = "serialize::io_error"))] Error),
+    ErrorWriting(#[cfg_attr(feature = "serde", serde(with = "serialize::io_error"))] Error),
+    ErrorDecoding(#[cfg_attr(feature = "serde", serde(with = "serialize::io_error"))] Error),
+}
+
+impl fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessError::ErrorWaiting(e) => write!(f, "ErrorWaiting: {}", e),
+            ProcessError::ErrorReading(e) => write!(f, "ErrorReading: {}", e),
+            ProcessError::ErrorHandling(e) => write!(f, "ErrorHandling: {}", e),
+            ProcessError::ErrorRestarting(e) => write!(f, "ErrorRestarting: {}", e),
+            ProcessError::ErrorWriting(e) => write!(f, "ErrorWriting: {}", e),
+            ProcessError::ErrorDecoding(e) => write!(f, "ErrorDecoding: {}", e),
         }
     }
 }
+
+impl Clone for ProcessError {
+    /// `io::Error` cannot be cloned, so the clone carries a new error with
+    /// the same kind and message.
+    fn clone(&self) -> Self {
+        let copy = |e: &Error| Error::new(e.kind(), e.to_string());
+        match self {
+            ProcessError::ErrorWaiting(e) => ProcessError::ErrorWaiting(copy(e)),
+            ProcessError::ErrorReading(e) => ProcessError::ErrorReading(copy(e)),
+            ProcessError::ErrorHandling(e) => ProcessError::ErrorHandling(copy(e)),
+            ProcessError::ErrorRestarting(e) => ProcessError::ErrorRestarting(copy(e)),
+            ProcessError::ErrorWriting(e) => ProcessError::ErrorWriting(copy(e)),
+            ProcessError::ErrorDecoding(e) => ProcessError::ErrorDecoding(copy(e)),
+        }
+    }
+}
+
+impl error::Error for ProcessError {}
+
+#[derive(Debug)]
+pub enum ManagerError {
+    ProcessUnknown,
+    NameConflict(String),
+    StdinClosed,
+    InvalidConfig(String),
+    NotReady(String),
+    SignalFailed(Error),
+    SpawnFailed { name: String, source: Error },
+    KillTimeout,
+    Io(Error),
+}
+
+impl fmt::Display for ManagerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManagerError::ProcessUnknown => write!(f, "process unknown"),
+            ManagerError::NameConflict(name) => write!(f, "name conflict: {}", name),
+            ManagerError::StdinClosed => write!(f, "stdin closed"),
+            ManagerError::InvalidConfig(reason) => write!(f, "invalid configuration: {}", reason),
+            ManagerError::NotReady(reason) => write!(f, "process not ready: {}", reason),
+            ManagerError::SignalFailed(e) => write!(f, "signal failed: {}", e),
+            ManagerError::SpawnFailed { name, source } => {
+                write!(f, "failed to spawn {}: {}", name, source)
+            }
+            ManagerError::KillTimeout => write!(f, "timed out waiting for process to die"),
+            ManagerError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl error::Error for ManagerError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ManagerError::SignalFailed(e)
+            | ManagerError::SpawnFailed { source: e, .. }
+            | ManagerError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for ManagerError {
+    fn from(e: Error) -> Self {
+        ManagerError::Io(e)
+    }
+}
+
+/// Whether a process should be started again after it exits, and at most how
+/// many times.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    OnFailure {
+        max: u32,
+    },
+    Always {
+        max: u32,
+    },
+}
+
+impl RestartPolicy {
+    /// Whether a process that exited with `status`, having already been
+    /// restarted `attempts` times, should be restarted once more.
+    fn permits(&self, status: ExitStatus, attempts: u32) -> bool {
+        match *self {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure { max } => !status.success() && attempts < max,
+            RestartPolicy::Always { max } => attempts < max,
+        }
+    }
+}
+
+/// A handle for telling a running director to return. Clones share the same
+/// flag, so one can be given to the director and another kept to cancel it.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// How `run_director_with_timeout` returned: either every process finished,
+/// or the deadline passed first. Both carry the exit status of each process
+/// that finished, by name.
+#[derive(Clone, Debug)]
+pub enum DirectorOutcome {
+    Finished(HashMap<String, ExitStatus>),
+    TimedOut {
+        statuses: HashMap<String, ExitStatus>,
+        running: Vec<String>,
+    },
+}
+
+/// The state of a managed process, as reported by `status`. `Unknown` means
+/// the operating system could not tell us whether it is still running.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ProcessStatus {
+    Running,
+    Exited(#[cfg_attr(feature = "serde", serde(with = "serialize::exit_status"))] ExitStatus),
+    Unknown,
+}
+
+/// Why a process exited: either it returned an exit code, or, on Unix, it
+/// was terminated by a signal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ExitReason {
+    Code(i32),
+    Signal(i32),
+}
+
+impl ExitReason {
+    /// The reason behind `status`, if the platform reports one.
+    pub fn of(status: &ExitStatus) -> Option<ExitReason> {
+        if let Some(code) = status.code() {
+            return Some(ExitReason::Code(code));
+        }
+        #[cfg(unix)]
+        return status.signal().map(ExitReason::Signal);
+        #[cfg(not(unix))]
+        None
+    }
+}
+
+/// How a retired process ended up, as returned by
+/// `ProcessManager::outcomes`: it succeeded, failed with an exit code, or was
+/// killed by a signal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Outcome {
+    Success,
+    Failed(i32),
+    Killed(i32),
+}
+
+impl Outcome {
+    /// The outcome that `status` reports.
+    pub fn of(status: &ExitStatus) -> Outcome {
+        match ExitReason::of(status) {
+            _ if status.success() => Outcome::Success,
+            Some(ExitReason::Code(code)) => Outcome::Failed(code),
+            Some(ExitReason::Signal(signal)) => Outcome::Killed(signal),
+            // Without a code to go by there is only the failure to report.
+            None => Outcome::Failed(-1),
+        }
+    }
+}
+
+/// Everything a command wrote, and how it exited, as returned by
+/// `ProcessManager::run_and_capture`.
+#[derive(Clone, Debug)]
+pub struct CaptureResult {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub status: ExitStatus,
+}
+
+/// How much memory and CPU time a managed process is using, as returned by
+/// `ProcessManager::resource_usage`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ResourceUsage {
+    /// The resident set size.
+    pub rss_bytes: u64,
+    /// The CPU time spent in user and kernel mode together.
+    pub cpu_seconds: f64,
+}
+
+/// Caps on the resources a process may use, as applied by
+/// `ProcessManager::run_process_with_limits`. A process that exceeds its CPU
+/// time is sent SIGXCPU, while one that exceeds the others sees its attempts
+/// to allocate memory or open files fail.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ResourceLimits {
+    /// The most address space, in bytes.
+    pub max_memory: Option<u64>,
+    /// The most CPU time, in seconds.
+    pub max_cpu_seconds: Option<u64>,
+    /// The most file descriptors open at once.
+    pub max_open_files: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// Set the limits on the calling process. This runs in the child between
+    /// fork and exec, so it must not allocate.
+    #[cfg(unix)]
+    fn apply(&self) -> Result<()> {
+        let set = |resource, soft: u64, hard: u64| {
+            let limit = libc::rlimit {
+                rlim_cur: soft as libc::rlim_t,
+                rlim_max: hard as libc::rlim_t,
+            };
+            if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+                return Err(Error::last_os_error());
+            }
+            Ok(())
+        };
+        if let Some(bytes) = self.max_memory {
+            set(libc::RLIMIT_AS, bytes, bytes)?;
+        }
+        if let Some(seconds) = self.max_cpu_seconds {
+            // Reaching the hard limit means SIGKILL, so leave a second
+            // between them for the SIGXCPU of the soft limit to be seen.
+            set(libc::RLIMIT_CPU, seconds, seconds + 1)?;
+        }
+        if let Some(files) = self.max_open_files {
+            set(libc::RLIMIT_NOFILE, files, files)?;
+        }
+        Ok(())
+    }
+}
+
+/// A process for `ProcessManager::start_ordered` to start, once the
+/// processes named in `depends_on` have started and are ready.
+#[derive(Debug)]
+pub struct ProcessSpec {
+    pub name: String,
+    pub command: Command,
+    /// A pattern that a line of stdout must match before the process is
+    /// ready, or `None` if it is ready as soon as it is spawned.
+    pub ready: Option<Regex>,
+    pub depends_on: Vec<String>,
+}
+
+impl ProcessSpec {
+    pub fn new(name: &str, command: Command) -> Self {
+        ProcessSpec {
+            name: name.to_string(),
+            command,
+            ready: None,
+            depends_on: Vec::new(),
+        }
+    }
+
+    pub fn with_ready(mut self, pattern: Regex) -> Self {
+        self.ready = Some(pattern);
+        self
+    }
+
+    pub fn with_dependency(mut self, name: &str) -> Self {
+        self.depends_on.push(name.to_string());
+        self
+    }
+}
+
+/// How a process ended up being stopped by `stop_process_graceful`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopOutcome {
+    Graceful,
+    Killed,
+}
+
+const DEFAULT_READ_BUFFER_SIZE: usize = 8192;
+
+/// The most reads joined into a single event when coalescing, so that a
+/// process that writes without pause cannot keep its monitor reading forever.
+const MAX_COALESCED_READS: usize = 64;
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ProcessEvent {
+    Exited(#[cfg_attr(feature = "serde", serde(with = "serialize::exit_status"))] ExitStatus),
+    /// The process was terminated by this signal. Reported just before its
+    /// `Exited`, which remains the last event.
+    Signaled(i32),
+    Error(ProcessError),
+    Output(HandleType, Vec<u8>),
+    Eof(HandleType),
+    Line(HandleType, String),
+    Text(HandleType, String),
+    Restarted {
+        attempt: u32,
+        delay: time::Duration,
+    },
+    Dropped(usize),
+    TimedOut,
+    Paused,
+    Resumed,
+    Throttled,
+    WatchdogTripped,
+}
+
+impl fmt::Display for ProcessEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessEvent::Exited(status) => write!(f, "Exited({})", status),
+            ProcessEvent::Signaled(signal) => write!(f, "Signaled({})", signal),
+            ProcessEvent::Error(err) => write!(f, "Error({})", err),
+            ProcessEvent::Output(handle, bytes) => write!(
+                f,
+                "Output({:?}, {:?}, {})",
+                handle,
+                String::from_utf8_lossy(bytes),
+                bytes.len()
+            ),
+            ProcessEvent::Eof(handle) => write!(f, "Eof({:?})", handle),
+            ProcessEvent::Line(handle, line) => write!(f, "Line({:?}, {:?})", handle, line),
+            ProcessEvent::Text(handle, text) => write!(f, "Text({:?}, {:?})", handle, text),
+            ProcessEvent::Restarted { attempt, delay } => {
+                write!(f, "Restarted({}, {:?})", attempt, delay)
+            }
+            ProcessEvent::Dropped(count) => write!(f, "Dropped({})", count),
+            ProcessEvent::TimedOut => write!(f, "TimedOut"),
+            ProcessEvent::Paused => write!(f, "Paused"),
+            ProcessEvent::Resumed => write!(f, "Resumed"),
+            ProcessEvent::Throttled => write!(f, "Throttled"),
+            ProcessEvent::WatchdogTripped => write!(f, "WatchdogTripped"),
+        }
+    }
+}
+
+impl ProcessEvent {
+    /// For an `Exited` event, whether the process returned an exit code or
+    /// was killed by a signal, and for a `Signaled` event, the signal.
+    pub fn exit_reason(&self) -> Option<ExitReason> {
+        match self {
+            ProcessEvent::Exited(status) => ExitReason::of(status),
+            ProcessEvent::Signaled(signal) => Some(ExitReason::Signal(*signal)),
+            _ => None,
+        }
+    }
+
+    /// The events that report the end of a process that exited with
+    /// `status`: a `Signaled`, if a signal terminated it, then `Exited`.
+    fn exit_events(status: ExitStatus) -> Vec<ProcessEvent> {
+        let mut events = Vec::new();
+        if let Some(ExitReason::Signal(signal)) = ExitReason::of(&status) {
+            events.push(ProcessEvent::Signaled(signal));
+        }
+        events.push(ProcessEvent::Exited(status));
+        events
+    }
+
+    /// For an `Exited` event, the code the process exited with, unless it
+    /// was killed by a signal.
+    pub fn exit_code(&self) -> Option<i32> {
+        match self {
+            ProcessEvent::Exited(status) => status.code(),
+            _ => None,
+        }
+    }
+}
+
+/// A `ProcessEvent` together with the time at which its process's monitoring
+/// loop produced it, so that the delay before the director delivered it can
+/// be measured.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TimedEvent {
+    pub at: time::SystemTime,
+    pub event: ProcessEvent,
+}
+
+impl TimedEvent {
+    fn now(event: ProcessEvent) -> Self {
+        TimedEvent {
+            at: time::SystemTime::now(),
+            event,
+        }
+    }
+}
+
+/// Put one of the child's pipes into non-blocking mode, so that a child which
+/// stays silent cannot stall its monitoring loop.
+#[cfg(unix)]
+fn set_nonblocking<T: AsRawFd>(handle: &T) -> Result<()> {
+    let fd = handle.as_raw_fd();
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+            return Err(Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// On Windows anonymous pipes cannot be made non-blocking, so this is a no-op
+/// for now and reads there still block until the child writes or exits.
+#[cfg(not(unix))]
+fn set_nonblocking<T>(_handle: &T) -> Result<()> {
+    Ok(())
+}
+
+/// Copy `bytes` without the ANSI CSI sequences in it, and return the copy
+/// along with the offset of a sequence cut off at the end, or else the length
+/// of `bytes`. Other escape sequences are copied as they are.
+fn strip_csi(bytes: &[u8]) -> (Vec<u8>, usize) {
+    let mut stripped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != 0x1b {
+            stripped.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        match bytes.get(i + 1) {
+            None => return (stripped, i),
+            // Parameter and intermediate bytes follow, up to a final byte.
+            Some(b'[') => match bytes[i + 2..]
+                .iter()
+                .position(|b| (0x40..=0x7e).contains(b))
+            {
+                Some(len) => i += len + 3,
+                None => return (stripped, i),
+            },
+            Some(_) => {
+                stripped.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    (stripped, bytes.len())
+}
+
+/// Read the resource usage of process `pid` from `/proc`.
+#[cfg(target_os = "linux")]
+fn read_resource_usage(pid: u32) -> Result<ResourceUsage> {
+    let invalid = || Error::new(ErrorKind::InvalidData, "unexpected contents in /proc");
+
+    // The fields after the parenthesised command name begin with the state,
+    // and the user and system times, in clock ticks, are the 12th and 13th.
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid))?;
+    let fields: Vec<&str> = stat[stat.rfind(')').ok_or_else(invalid)? + 2..]
+        .split(' ')
+        .collect();
+    let ticks = |i: usize| -> Result<u64> {
+        let field = fields.get(i).ok_or_else(invalid)?;
+        field.parse().map_err(|_| invalid())
+    };
+    let ticks_per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    let cpu_seconds = (ticks(11)? + ticks(12)?) as f64 / ticks_per_second as f64;
+
+    // A process that has exited but not been reaped has no memory left.
+    let status = fs::read_to_string(format!("/proc/{}/status", pid))?;
+    let rss_kb = match status.lines().find_map(|l| l.strip_prefix("VmRSS:")) {
+        Some(rss) => rss
+            .trim()
+            .trim_end_matches("kB")
+            .trim()
+            .parse()
+            .map_err(|_| invalid())?,
+        None => 0,
+    };
+
+    Ok(ResourceUsage {
+        rss_bytes: rss_kb * 1024,
+        cpu_seconds,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_resource_usage(_pid: u32) -> Result<ResourceUsage> {
+    Err(Error::new(
+        ErrorKind::Unsupported,
+        "resource usage is not supported on this platform",
+    ))
+}
+
+/// The program and arguments of `command`, lossily converted to strings.
+fn command_line(command: &Command) -> Vec<String> {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// The length of `bytes` without a UTF-8 character cut off at the end, if
+/// there is one. Invalid bytes are left for the decoder to deal with.
+fn complete_utf8_len(bytes: &[u8]) -> usize {
+    // A character takes at most four bytes, so only the last three can
+    // belong to one that is incomplete.
+    for back in 1..=std::cmp::min(3, bytes.len()) {
+        let start = bytes.len() - back;
+        let width = match bytes[start] {
+            0x80..=0xbf => continue,
+            0xc2..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf4 => 4,
+            _ => return bytes.len(),
+        };
+        return if back < width { start } else { bytes.len() };
+    }
+    bytes.len()
+}
+
+/// Spawn `command` with stdin, stdout, and stderr sent where `stdin`,
+/// `stdout` and `stderr` say. The pipes to us are made non-blocking.
+fn spawn_child(
+    command: &mut Command,
+    process_group: bool,
+    stdin: Stdio,
+    stdout: Stdio,
+    stderr: Stdio,
+) -> Result<Child> {
+    #[cfg(unix)]
+    if process_group {
+        // Equivalent to calling `setpgid(0, 0)` in the child before exec.
+        command.process_group(0);
+    }
+    #[cfg(not(unix))]
+    let _ = process_group;
+
+    let child = command.stdin(stdin).stdout(stdout).stderr(stderr).spawn()?;
+    if let Some(h) = &child.stdin {
+        set_nonblocking(h)?;
+    }
+    if let Some(h) = &child.stdout {
+        set_nonblocking(h)?;
+    }
+    if let Some(h) = &child.stderr {
+        set_nonblocking(h)?;
+    }
+    Ok(child)
+}
+
+impl ProcessControl {
+    /// Send `signal` to the child. The caller holds the lock on this control
+    /// and we make sure the child has not been reaped, so that its pid cannot
+    /// have been reused by the time we signal it.
+    #[cfg(unix)]
+    fn signal(&mut self, signal: i32) -> std::result::Result<(), ManagerError> {
+        if self.child.try_wait()?.is_some() {
+            return Err(ManagerError::SignalFailed(Error::from_raw_os_error(
+                libc::ESRCH,
+            )));
+        }
+        if unsafe { libc::kill(self.child.id() as libc::pid_t, signal) } != 0 {
+            return Err(ManagerError::SignalFailed(Error::last_os_error()));
+        }
+        Ok(())
+    }
+
+    /// The pid to signal to reach the child, which is negated to reach its
+    /// whole process group if it leads one.
+    #[cfg(unix)]
+    fn signal_target(&self) -> libc::pid_t {
+        let pid = self.child.id() as libc::pid_t;
+        if self.process_group {
+            -pid
+        } else {
+            pid
+        }
+    }
+
+    /// On Windows, put the child in a job object of its own if it is to lead
+    /// a group, which stands in for the process group it would lead on Unix.
+    /// Any job of a previous child is closed, killing what is left in it.
+    #[cfg(windows)]
+    fn contain(&mut self) -> Result<()> {
+        self.job = None;
+        if self.process_group {
+            self.job = Some(job::Job::for_child(&self.child)?);
+        }
+        Ok(())
+    }
+
+    /// Kill the child, along with the rest of its process group if it leads
+    /// one. The group can outlive the child while any descendants remain.
+    /// The child is reaped straight away rather than left as a zombie until
+    /// its monitoring loop next polls it, waiting at most `timeout` for it
+    /// to die if one is given.
+    fn kill(&mut self, timeout: Option<time::Duration>) -> std::result::Result<(), ManagerError> {
+        #[cfg(unix)]
+        if self.process_group && unsafe { libc::kill(self.signal_target(), libc::SIGKILL) } != 0 {
+            let e = Error::last_os_error();
+            if e.raw_os_error() != Some(libc::ESRCH) {
+                return Err(e.into());
+            }
+        }
+        #[cfg(windows)]
+        if let Some(job) = &self.job {
+            job.terminate()?;
+        }
+        self.child.kill()?;
+
+        let timeout = match timeout {
+            Some(timeout) => timeout,
+            None => {
+                self.child.wait()?;
+                return Ok(());
+            }
+        };
+        let deadline = self.clock.now() + timeout;
+        while self.child.try_wait()?.is_none() {
+            if self.clock.now() >= deadline {
+                return Err(ManagerError::KillTimeout);
+            }
+            self.clock.sleep(time::Duration::from_millis(10));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn signal(&mut self, _signal: i32) -> std::result::Result<(), ManagerError> {
+        Err(ManagerError::SignalFailed(Error::new(
+            ErrorKind::Unsupported,
+            "signals are not supported on this platform",
+        )))
+    }
+
+    fn is_open(&self, handle: HandleType) -> bool {
+        match handle {
+            HandleType::StdInput => self.child.stdin.is_some(),
+            HandleType::StdOutput => self.child.stdout.is_some(),
+            HandleType::StdError => self.child.stderr.is_some(),
+        }
+    }
+
+    /// Read the next chunk of output from `handle`, if it is still open and
+    /// has data available. A zero-length read means the child closed its end
+    /// of the pipe, so the handle is dropped and `Eof` is reported exactly
+    /// once. Having nothing to read yet is not an error, and neither is being
+    /// interrupted, after which the read is retried.
+    fn read_output(&mut self, handle: HandleType, buf: &mut [u8]) -> Option<ProcessEvent> {
+        let result = loop {
+            let result = match handle {
+                HandleType::StdInput => None,
+                HandleType::StdOutput => self.child.stdout.as_mut().map(|h| h.read(buf)),
+                HandleType::StdError => self.child.stderr.as_mut().map(|h| h.read(buf)),
+            }?;
+            // A read cut short by a signal is simply tried again.
+            match result {
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                result => break result,
+            }
+        };
+        Some(match result {
+            Ok(0) => {
+                match handle {
+                    HandleType::StdInput => self.child.stdin = None,
+                    HandleType::StdOutput => self.child.stdout = None,
+                    HandleType::StdError => self.child.stderr = None,
+                }
+                ProcessEvent::Eof(handle)
+            }
+            Ok(len) => {
+                self.count_read(handle, len);
+                ProcessEvent::Output(handle, buf[..len].to_vec())
+            }
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => return None,
+            Err(e) => ProcessEvent::Error(ProcessError::ErrorReading(e)),
+        })
+    }
+
+    /// Add `len` bytes to the total read from `handle`.
+    fn count_read(&mut self, handle: HandleType, len: usize) {
+        match handle {
+            HandleType::StdInput => {}
+            HandleType::StdOutput => self.stdout_read += len as u64,
+            HandleType::StdError => self.stderr_read += len as u64,
+        }
+    }
+
+    /// Like `read_output`, but read no more than the rate limit allows, if
+    /// there is one. Reports `Throttled` when the limit first holds output
+    /// back, and again only after the process has since caught up.
+    fn read_limited(
+        &mut self,
+        handle: HandleType,
+        buf: &mut [u8],
+        now: time::Instant,
+    ) -> Option<ProcessEvent> {
+        let limiter = match &mut self.rate_limiter {
+            Some(limiter) => limiter,
+            None => return self.read_output(handle, buf),
+        };
+        limiter.refill(now);
+        if limiter.allowance == 0 {
+            if limiter.throttled {
+                return None;
+            }
+            limiter.throttled = true;
+            return Some(ProcessEvent::Throttled);
+        }
+        let len = std::cmp::min(buf.len() as u64, limiter.allowance) as usize;
+        let ev = self.read_output(handle, &mut buf[..len]);
+        if let Some(limiter) = &mut self.rate_limiter {
+            match &ev {
+                Some(ProcessEvent::Output(_handle, bytes)) => {
+                    limiter.allowance -= bytes.len() as u64
+                }
+                None => limiter.throttled = false,
+                Some(_) => {}
+            }
+        }
+        ev
+    }
+
+    /// Write as much of the buffered input to the child's stdin as the pipe
+    /// takes without blocking, and close stdin once the buffer is empty if
+    /// that was asked for. If writing fails, stdin is closed and the rest of
+    /// the input discarded.
+    fn flush_stdin(&mut self) -> Result<()> {
+        let stdin = match &mut self.child.stdin {
+            Some(stdin) => stdin,
+            None => {
+                self.stdin_buffer.clear();
+                return Ok(());
+            }
+        };
+        while !self.stdin_buffer.is_empty() {
+            let (pending, _) = self.stdin_buffer.as_slices();
+            let result = match stdin.write(pending) {
+                Ok(0) => Err(Error::from(ErrorKind::WriteZero)),
+                result => result,
+            };
+            match result {
+                Ok(len) => {
+                    self.stdin_buffer.drain(..len);
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.stdin_buffer.clear();
+                    self.child.stdin = None;
+                    return Err(e);
+                }
+            }
+        }
+        if self.closing_stdin {
+            self.child.stdin = None;
+        }
+        Ok(())
+    }
+
+    /// Read `handle` until nothing more is waiting, joining what was read
+    /// into one `Output` event, followed by `Eof` or an error if reading
+    /// ended with one.
+    fn read_coalesced(
+        &mut self,
+        handle: HandleType,
+        buf: &mut [u8],
+        now: time::Instant,
+    ) -> Vec<ProcessEvent> {
+        let mut bytes = Vec::new();
+        let mut events = Vec::new();
+        for _ in 0..MAX_COALESCED_READS {
+            match self.read_limited(handle, buf, now) {
+                Some(ProcessEvent::Output(_handle, chunk)) => bytes.extend(chunk),
+                Some(ev) => {
+                    events.push(ev);
+                    break;
+                }
+                None => break,
+            }
+        }
+        if !bytes.is_empty() {
+            events.insert(0, ProcessEvent::Output(handle, bytes));
+        }
+        events
+    }
+
+    /// The bytes of a UTF-8 character that the last output on `handle` cut
+    /// off, still to be decoded along with the rest of it.
+    fn partial_text(&mut self, handle: HandleType) -> Option<&mut Vec<u8>> {
+        match handle {
+            HandleType::StdInput => None,
+            HandleType::StdOutput => Some(&mut self.stdout_text),
+            HandleType::StdError => Some(&mut self.stderr_text),
+        }
+    }
+
+    /// Turn an `Output` event into a `Text` event, decoding its bytes with
+    /// `encoding`, or into an error if they cannot be. A UTF-8 character cut
+    /// off at the end is held back until the rest of it arrives. Should the
+    /// handle reach end-of-file first, what is left is decoded lossily, even
+    /// with `Utf8Strict`, since no more output can complete it. Other events
+    /// are passed through.
+    fn decode_text(&mut self, ev: ProcessEvent, encoding: Encoding) -> Vec<ProcessEvent> {
+        #[cfg(feature = "encoding_rs")]
+        if let Encoding::Named(encoding) = encoding {
+            return self.decode_named(ev, encoding);
+        }
+        match ev {
+            ProcessEvent::Output(handle, bytes) => {
+                let mut bytes = bytes;
+                if encoding != Encoding::Latin1 {
+                    if let Some(partial) = self.partial_text(handle) {
+                        partial.extend_from_slice(&bytes);
+                        let end = complete_utf8_len(partial);
+                        bytes = partial.drain(..end).collect();
+                    }
+                }
+                if bytes.is_empty() {
+                    return Vec::new();
+                }
+                vec![match encoding.decode(&bytes) {
+                    Ok(text) => ProcessEvent::Text(handle, text),
+                    Err(e) => ProcessEvent::Error(ProcessError::ErrorDecoding(e)),
+                }]
+            }
+            ProcessEvent::Eof(handle) => {
+                let mut events = Vec::new();
+                if let Some(partial) = self.partial_text(handle) {
+                    if !partial.is_empty() {
+                        let text = String::from_utf8_lossy(partial).into_owned();
+                        partial.clear();
+                        events.push(ProcessEvent::Text(handle, text));
+                    }
+                }
+                events.push(ProcessEvent::Eof(handle));
+                events
+            }
+            ev => vec![ev],
+        }
+    }
+
+    /// Like `decode_text`, for an encoding of `encoding_rs`. Each handle has
+    /// a decoder of its own, which holds on to a character cut off at the
+    /// end of one read until the next, and is finished at end-of-file.
+    #[cfg(feature = "encoding_rs")]
+    fn decode_named(
+        &mut self,
+        ev: ProcessEvent,
+        encoding: &'static encoding_rs::Encoding,
+    ) -> Vec<ProcessEvent> {
+        let (handle, bytes, last) = match &ev {
+            ProcessEvent::Output(handle, bytes) => (*handle, &bytes[..], false),
+            ProcessEvent::Eof(handle) => (*handle, &[][..], true),
+            _ => return vec![ev],
+        };
+        let decoder = match handle {
+            HandleType::StdInput => return vec![ev],
+            HandleType::StdOutput => &mut self.stdout_decoder,
+            HandleType::StdError => &mut self.stderr_decoder,
+        };
+        let mut text = String::new();
+        {
+            let decoder =
+                decoder.get_or_insert_with(|| encoding.new_decoder_without_bom_handling());
+            text.reserve(
+                decoder
+                    .max_utf8_buffer_length(bytes.len())
+                    .unwrap_or(bytes.len()),
+            );
+            let _ = decoder.decode_to_string(bytes, &mut text, last);
+        }
+        if last {
+            *decoder = None;
+        }
+
+        let mut events = Vec::new();
+        if !text.is_empty() {
+            events.push(ProcessEvent::Text(handle, text));
+        }
+        if last {
+            events.push(ev);
+        }
+        events
+    }
+
+    /// Begin the text of a `Line` event, or each line begun in a `Text`
+    /// event, with the name of the process in brackets. Whether a handle's
+    /// text left off in the middle of a line is remembered until the next
+    /// `Text` event, or its end-of-file. Other events are passed through.
+    fn prefix_name(&mut self, ev: ProcessEvent) -> ProcessEvent {
+        let mid_line = match ev {
+            ProcessEvent::Text(HandleType::StdError, _)
+            | ProcessEvent::Eof(HandleType::StdError) => &mut self.stderr_mid_line,
+            _ => &mut self.stdout_mid_line,
+        };
+        match ev {
+            ProcessEvent::Line(handle, line) => {
+                ProcessEvent::Line(handle, format!("[{}] {}", self.name, line))
+            }
+            ProcessEvent::Text(handle, text) => {
+                let mut prefixed = String::with_capacity(text.len());
+                for line in text.split_inclusive('\n') {
+                    if !*mid_line {
+                        prefixed.push_str(&format!("[{}] ", self.name));
+                    }
+                    prefixed.push_str(line);
+                    *mid_line = !line.ends_with('\n');
+                }
+                ProcessEvent::Text(handle, prefixed)
+            }
+            ProcessEvent::Eof(handle) => {
+                *mid_line = false;
+                ProcessEvent::Eof(handle)
+            }
+            ev => ev,
+        }
+    }
+
+    /// Log the lifecycle events of the child that the manager produces.
+    #[cfg(feature = "log")]
+    fn log(&self, ev: &ProcessEvent) {
+        let pid = self.child.id();
+        match ev {
+            ProcessEvent::Exited(status) => {
+                log::info!("process {} (pid {}) exited: {}", self.name, pid, status)
+            }
+            ProcessEvent::Restarted { attempt, .. } => log::warn!(
+                "process {} restarted as pid {} (attempt {})",
+                self.name,
+                pid,
+                attempt
+            ),
+            ProcessEvent::Error(err) => log::error!("process {} (pid {}): {}", self.name, pid, err),
+            _ => (),
+        }
+    }
+
+    /// A span covering the monitoring of the child, under which its events
+    /// are recorded. It is created on first use and kept, so that `rename`
+    /// can update it.
+    #[cfg(feature = "tracing")]
+    fn span(&mut self) -> tracing::Span {
+        if self.span.is_none() {
+            self.span = tracing::info_span!("process", name = %self.name, pid = self.child.id());
+        }
+        self.span.clone()
+    }
+
+    /// Give the process a new name, in its span and log lines as well as in
+    /// any prefix added to its output from now on.
+    fn rename(&mut self, name: &str) {
+        #[cfg(feature = "log")]
+        log::info!(
+            "process {} (pid {}) renamed to {}",
+            self.name,
+            self.child.id(),
+            name
+        );
+        self.name = name.to_string();
+        #[cfg(feature = "tracing")]
+        self.span.record("name", tracing::field::display(name));
+    }
+
+    fn tee_file(&mut self, handle: HandleType) -> Option<&mut io::BufWriter<fs::File>> {
+        match handle {
+            HandleType::StdInput => None,
+            HandleType::StdOutput => self.stdout_tee.as_mut(),
+            HandleType::StdError => self.stderr_tee.as_mut(),
+        }
+    }
+
+    /// Copy output to the tee file for its handle, if it has one, flushing
+    /// the file once the handle reaches end-of-file.
+    fn tee(&mut self, ev: &ProcessEvent) -> Result<()> {
+        match ev {
+            ProcessEvent::Output(handle, bytes) => match self.tee_file(*handle) {
+                Some(tee) => tee.write_all(bytes),
+                None => Ok(()),
+            },
+            ProcessEvent::Eof(handle) => match self.tee_file(*handle) {
+                Some(tee) => tee.flush(),
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn flush_tees(&mut self) -> Result<()> {
+        self.stdout_tee
+            .iter_mut()
+            .chain(self.stderr_tee.iter_mut())
+            .try_for_each(|tee| tee.flush())
+    }
+
+    fn partial_escape(&mut self, handle: HandleType) -> Option<&mut Vec<u8>> {
+        match handle {
+            HandleType::StdInput => None,
+            HandleType::StdOutput => Some(&mut self.stdout_escape),
+            HandleType::StdError => Some(&mut self.stderr_escape),
+        }
+    }
+
+    /// Remove ANSI CSI sequences from raw output. A sequence cut off at the
+    /// end of the output is held back until the rest of it arrives, and at
+    /// end-of-file, if it never did, is passed on as it is.
+    fn strip_ansi(&mut self, ev: ProcessEvent) -> Vec<ProcessEvent> {
+        let mut events = Vec::new();
+        match ev {
+            ProcessEvent::Output(handle, bytes) => match self.partial_escape(handle) {
+                Some(partial) => {
+                    partial.extend_from_slice(&bytes);
+                    let (stripped, end) = strip_csi(partial);
+                    partial.drain(..end);
+                    if !stripped.is_empty() {
+                        events.push(ProcessEvent::Output(handle, stripped));
+                    }
+                }
+                None => events.push(ProcessEvent::Output(handle, bytes)),
+            },
+            ProcessEvent::Eof(handle) => {
+                if let Some(partial) = self.partial_escape(handle) {
+                    if !partial.is_empty() {
+                        events.push(ProcessEvent::Output(handle, std::mem::take(partial)));
+                    }
+                }
+                events.push(ProcessEvent::Eof(handle));
+            }
+            ev => events.push(ev),
+        }
+        events
+    }
+
+    fn partial_line(&mut self, handle: HandleType) -> Option<&mut Vec<u8>> {
+        match handle {
+            HandleType::StdInput => None,
+            HandleType::StdOutput => Some(&mut self.stdout_line),
+            HandleType::StdError => Some(&mut self.stderr_line),
+        }
+    }
+
+    /// Turn raw output into one `Line` event per complete line, holding back
+    /// any trailing partial line until more output arrives. At end-of-file
+    /// the remainder is flushed as a final line before the `Eof` itself.
+    fn split_lines(&mut self, ev: ProcessEvent) -> Vec<ProcessEvent> {
+        let mut events = Vec::new();
+        match ev {
+            ProcessEvent::Output(handle, bytes) => match self.partial_line(handle) {
+                Some(partial) => {
+                    partial.extend_from_slice(&bytes);
+                    while let Some(pos) = partial.iter().position(|b| *b == b'\n') {
+                        let line: Vec<u8> = partial.drain(..=pos).collect();
+                        events.push(ProcessEvent::Line(
+                            handle,
+                            String::from_utf8_lossy(&line[..pos]).into_owned(),
+                        ));
+                    }
+                }
+                None => events.push(ProcessEvent::Output(handle, bytes)),
+            },
+            ProcessEvent::Eof(handle) => {
+                if let Some(partial) = self.partial_line(handle) {
+                    if !partial.is_empty() {
+                        let line = std::mem::take(partial);
+                        events.push(ProcessEvent::Line(
+                            handle,
+                            String::from_utf8_lossy(&line).into_owned(),
+                        ));
+                    }
+                }
+                events.push(ProcessEvent::Eof(handle));
+            }
+            ev => events.push(ev),
+        }
+        events
+    }
+}
+
+impl ProcessManager {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Create a manager with the settings in `config`, which are shared by
+    /// all of its clones.
+    pub fn with_config(config: ProcessManagerConfig) -> Self {
+        let kill_on_drop = config.kill_on_drop;
+        ProcessManager {
+            config: Arc::new(config),
+            ..Default::default()
+        }
+        .with_kill_on_drop(kill_on_drop)
+    }
+
+    /// A clone for a thread of our own, which does not keep the manager from
+    /// being dropped.
+    fn background_clone(&self) -> Self {
+        ProcessManager {
+            kill_on_drop: None,
+            ..self.clone()
+        }
+    }
+
+    pub fn config(&self) -> &ProcessManagerConfig {
+        &self.config
+    }
+
+    /// Run `command` to completion outside of any manager, collecting all of
+    /// its output along with its exit status.
+    pub fn run_and_capture(
+        command: &mut Command,
+    ) -> std::result::Result<CaptureResult, ManagerError> {
+        let output = command.output()?;
+        Ok(CaptureResult {
+            stdout: output.stdout,
+            stderr: output.stderr,
+            status: output.status,
+        })
+    }
+
+    /// Set how long the director and monitoring loops sleep between polls.
+    /// Shorter intervals reduce event latency at the cost of more wakeups.
+    pub fn with_poll_interval(mut self, interval: time::Duration) -> Self {
+        Arc::make_mut(&mut self.config).poll_interval = interval;
+        self
+    }
+
+    pub fn poll_interval(&self) -> time::Duration {
+        self.config.poll_interval
+    }
+
+    /// Set the size of the buffer used for each read from a child's output
+    /// pipes, which is also the largest payload a single `Output` carries.
+    pub fn with_read_buffer_size(mut self, size: usize) -> Self {
+        Arc::make_mut(&mut self.config).read_buffer_size = size;
+        self
+    }
+
+    pub fn read_buffer_size(&self) -> usize {
+        self.config.read_buffer_size
+    }
+
+    /// When enabled, output is reported as one `Line` event per line of text
+    /// (without its trailing newline) rather than as raw `Output` chunks.
+    /// Lines that are not valid UTF-8 are decoded lossily.
+    pub fn with_line_buffering(mut self, enabled: bool) -> Self {
+        Arc::make_mut(&mut self.config).line_buffering = enabled;
+        self
+    }
+
+    /// When enabled, output from both stdout and stderr is reported in the
+    /// order it was read as a single stream, whose `Output` (or `Line`)
+    /// events always carry `HandleType::StdOutput`. `Eof` still names the
+    /// handle that was closed.
+    pub fn with_merged_output(mut self, enabled: bool) -> Self {
+        Arc::make_mut(&mut self.config).merged_output = enabled;
+        self
+    }
+
+    /// When enabled, each child is made the leader of a new process group on
+    /// Unix, and stopping it kills the whole group, so that any processes it
+    /// started are not left behind. On Windows each child is put in a job
+    /// object of its own instead, which has the same effect, and also kills
+    /// whatever is left in the job once the process has been retired.
+    pub fn with_process_groups(mut self, enabled: bool) -> Self {
+        Arc::make_mut(&mut self.config).process_groups = enabled;
+        self
+    }
+
+    /// Set the variables in `env` in the environment of every process the
+    /// manager starts, except where its `Command` sets them itself.
+    pub fn with_default_env(mut self, env: HashMap<String, String>) -> Self {
+        Arc::make_mut(&mut self.config).default_env = env;
+        self
+    }
+
+    /// Start every process in the directory `cwd`, unless its `Command`
+    /// chooses a directory itself.
+    pub fn with_default_cwd(mut self, cwd: PathBuf) -> Self {
+        Arc::make_mut(&mut self.config).default_cwd = Some(cwd);
+        self
+    }
+
+    /// Keep the last `lines` lines of output of each process, from stdout and
+    /// stderr alike, to be fetched with `recent_output`.
+    pub fn with_output_history(mut self, lines: usize) -> Self {
+        Arc::make_mut(&mut self.config).output_history = lines;
+        self
+    }
+
+    /// Pipe and report only the output handles in `handles`, discarding the
+    /// others, unless a process sends them elsewhere with an `OutputTarget`.
+    pub fn with_handles(mut self, handles: HandleSet) -> Self {
+        Arc::make_mut(&mut self.config).handles = handles;
+        self
+    }
+
+    /// Kill every managed process once the last clone of this manager is
+    /// dropped, rather than leaving them running. Threads that the manager
+    /// runs on its own behalf do not count as clones. Set this before making
+    /// any clones, since those made earlier do not share it.
+    pub fn with_kill_on_drop(mut self, kill_on_drop: bool) -> Self {
+        Arc::make_mut(&mut self.config).kill_on_drop = kill_on_drop;
+        self.kill_on_drop = kill_on_drop.then(|| {
+            Arc::new(KillOnDrop {
+                processes: self.processes.clone(),
+                kill_timeout: self.config.kill_timeout,
+            })
+        });
+        self
+    }
+
+    /// Give up waiting for a killed process to die after `timeout`, reporting
+    /// `ManagerError::KillTimeout`, rather than waiting indefinitely.
+    pub fn with_kill_timeout(mut self, timeout: time::Duration) -> Self {
+        Arc::make_mut(&mut self.config).kill_timeout = Some(timeout);
+        if let Some(kill_on_drop) = self.kill_on_drop.as_mut().and_then(Arc::get_mut) {
+            kill_on_drop.kill_timeout = Some(timeout);
+        }
+        self
+    }
+
+    /// Read all the output waiting on a handle at each poll, and report it as
+    /// one `Output` event, rather than reporting one read of at most the
+    /// read buffer size per poll. This makes for fewer events when output
+    /// comes in bursts.
+    pub fn with_coalesce(mut self, coalesce: bool) -> Self {
+        Arc::make_mut(&mut self.config).coalesce = coalesce;
+        self
+    }
+
+    /// Read at most `bytes_per_sec` bytes of output a second from each
+    /// process, so that one flooding its output cannot overwhelm whoever
+    /// consumes the events. Output beyond the limit is left in the pipe until
+    /// there is allowance for it, which eventually makes the process wait to
+    /// write more; a `Throttled` event reports when that starts. Each process
+    /// has an allowance of its own, of up to one second's worth, which is
+    /// only waived for what is left to read once it has exited. Processes
+    /// run with `spawn_async` are not limited.
+    pub fn with_output_rate_limit(mut self, bytes_per_sec: u64) -> Self {
+        Arc::make_mut(&mut self.config).output_rate_limit = Some(bytes_per_sec);
+        self
+    }
+
+    /// Kill any process that goes without writing output for `idle`, and
+    /// report `WatchdogTripped`, so that one stuck without exiting is noticed.
+    /// The process may then be restarted, as its restart policy says. Time
+    /// spent paused does not count, and processes run with `spawn_async` are
+    /// not watched.
+    pub fn with_watchdog(mut self, idle: time::Duration) -> Self {
+        Arc::make_mut(&mut self.config).watchdog = Some(idle);
+        self
+    }
+
+    /// Report output as `Text` events, decoded with `encoding`, instead of
+    /// `Output` events. Output that `encoding` cannot decode is reported as
+    /// an `ErrorDecoding` error in its place. A UTF-8 character split across
+    /// reads is put back together, and one left incomplete when the output
+    /// ends is decoded lossily, as U+FFFD, whatever the encoding. Output split
+    /// into lines by line buffering is not affected.
+    pub fn with_text_output(mut self, encoding: Encoding) -> Self {
+        Arc::make_mut(&mut self.config).text_output = Some(encoding);
+        self
+    }
+
+    /// Begin every line of `Line` and `Text` events with the name of its
+    /// process in brackets, as in `[foo] hello`, so that the output of many
+    /// processes can be told apart once mixed together. A line split across
+    /// several `Text` events is prefixed only in the first. `Output` events
+    /// are left as they are.
+    pub fn with_name_prefix(mut self, enabled: bool) -> Self {
+        Arc::make_mut(&mut self.config).name_prefix = enabled;
+        self
+    }
+
+    /// Take the time from `clock`, and wait between polls by sleeping on it,
+    /// rather than using the system's clock. With a `MockClock`, timeouts,
+    /// restart backoff, and uptimes can be tested by advancing it instead of
+    /// waiting for them, though nothing that polls moves until it is.
+    pub fn with_clock<C>(mut self, clock: C) -> Self
+    where
+        C: Clock + 'static,
+    {
+        Arc::make_mut(&mut self.config).clock = Arc::new(clock);
+        self
+    }
+
+    /// Call `hook` with the name and exit status of each process once, when
+    /// the director has delivered its `Exited` event and removes it from the
+    /// table, whatever the director's callback did with the event. Detached
+    /// processes are never retired, so `hook` is not called for them.
+    pub fn with_exit_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&str, ExitStatus) + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.config).exit_hook = Some(ExitHook::new(hook));
+        self
+    }
+
+    /// Remove ANSI CSI sequences, such as those that set colours, from the
+    /// output of every process before it is reported or kept in the history.
+    /// Output sent to a file, or copied to one by `OutputTarget::Tee`, is left
+    /// as it was written.
+    pub fn with_strip_ansi(mut self, strip_ansi: bool) -> Self {
+        Arc::make_mut(&mut self.config).strip_ansi = strip_ansi;
+        self
+    }
+
+    /// Open the file at `path` as the stdin of every process, so that its
+    /// contents go to the child without passing through the manager. Input
+    /// can then no longer be sent with `send_input`, which reports
+    /// `InvalidConfig` instead.
+    pub fn with_stdin_from_file(mut self, path: PathBuf) -> Self {
+        Arc::make_mut(&mut self.config).stdin_file = Some(path);
+        self
+    }
+
+    /// Start every process with an empty environment, rather than a copy of
+    /// ours, apart from the variables its command sets and those given to
+    /// `with_default_env`.
+    pub fn with_env_clear(mut self, env_clear: bool) -> Self {
+        Arc::make_mut(&mut self.config).env_clear = env_clear;
+        self
+    }
+
+    /// Run `hook` in every child after it is forked and before its command is
+    /// executed, for instance to drop privileges or rearrange file
+    /// descriptors. If it fails, spawning fails with its error. Only Unix is
+    /// supported; elsewhere spawning is refused with `InvalidConfig`.
+    ///
+    /// # Safety
+    ///
+    /// The same as for `PreExecHook::new`.
+    pub unsafe fn with_pre_exec<F>(mut self, hook: F) -> Self
+    where
+        F: Fn() -> Result<()> + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.config).pre_exec = Some(PreExecHook::new(hook));
+        self
+    }
+
+    /// Give up waiting in `run_process_until_ready` after `timeout`. By
+    /// default it waits for as long as the process runs.
+    pub fn with_ready_timeout(mut self, timeout: time::Duration) -> Self {
+        Arc::make_mut(&mut self.config).ready_timeout = Some(timeout);
+        self
+    }
+
+    /// Limit how many events may be queued for each process. Once its queue
+    /// is full, a process's monitoring loop waits for the director to catch
+    /// up before reading any more output, so a slow director slows down the
+    /// producers rather than letting the queues grow without bound.
+    pub fn with_event_capacity(mut self, capacity: usize) -> Self {
+        Arc::make_mut(&mut self.config).event_capacity = Some(capacity);
+        self
+    }
+
+    /// Choose what happens to new events for a process whose queue is full.
+    /// Dropping events only makes sense for bounded queues, so a policy
+    /// other than `Block` also requires `with_event_capacity` or
+    /// `with_total_event_memory`.
+    pub fn with_overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        Arc::make_mut(&mut self.config).overflow_policy = policy;
+        self
+    }
+
+    /// Limit how many bytes of output may be queued by all processes
+    /// together, as a ceiling on top of any per-process capacity. Once the
+    /// budget is spent, the overflow policy decides what happens to new
+    /// output, with `DropOldest` making room in the queue of the process
+    /// producing it. The budget is shared by all clones of the manager.
+    pub fn with_total_event_memory(mut self, bytes: usize) -> Self {
+        Arc::make_mut(&mut self.config).total_event_memory = Some(bytes);
+        self
+    }
+
+    /// Wait before each automatic restart, starting at `initial` and doubling
+    /// after every restart up to `max`. A process that stays up for longer
+    /// than `max` is considered healthy again, and the wait resets to
+    /// `initial`.
+    pub fn with_restart_backoff(mut self, initial: time::Duration, max: time::Duration) -> Self {
+        Arc::make_mut(&mut self.config).restart_backoff = Some((initial, max));
+        self
+    }
+
+    /// Deliver queued events from every managed process to `on_event` until
+    /// all processes have exited, or until `stop` returns true, returning
+    /// the exit status of each process that exited by name.
+    fn direct<S, F>(&self, stop: S, on_event: F) -> Result<HashMap<String, ExitStatus>>
+    where
+        S: Fn() -> bool,
+        F: Fn(&str, TimedEvent, &mut dyn FnMut(ProcessEvent)),
+    {
+        let mut statuses = HashMap::new();
+        loop {
+            if stop() || self.closed.is_cancelled() {
+                // Whatever was queued by then is still delivered.
+                self.deliver_queued(None, &on_event, &mut statuses);
+                return Ok(statuses);
+            }
+
+            self.config.clock.sleep(self.config.poll_interval);
+
+            if !self.deliver_queued(Some(1), &on_event, &mut statuses) {
+                return Ok(statuses);
+            }
+        }
+    }
+
+    /// Make one pass over the queues of all managed processes, delivering up
+    /// to `limit` events from each, or otherwise all those already queued,
+    /// and retiring the processes whose exit was delivered. Returns false if
+    /// there were no processes.
+    fn deliver_queued<F>(
+        &self,
+        limit: Option<usize>,
+        on_event: &F,
+        statuses: &mut HashMap<String, ExitStatus>,
+    ) -> bool
+    where
+        F: Fn(&str, TimedEvent, &mut dyn FnMut(ProcessEvent)),
+    {
+        let mut to_remove: Vec<(String, Arc<RwLock<ProcessControl>>, ExitStatus)> = Vec::new();
+
+        // Take a snapshot of the table, so that it is not locked while
+        // `on_event` runs and the rest of the manager remains usable.
+        let processes = self.snapshot();
+
+        if processes.is_empty() {
+            return false;
+        }
+
+        for (name, ctl) in &processes {
+            let queue = ctl.read().unwrap().event_queue.clone();
+            // Stop at the events queued so far, even if more keep coming.
+            for _ in 0..limit.unwrap_or_else(|| queue.len()) {
+                let ev = match queue.pop() {
+                    Some(ev) => ev,
+                    None => break,
+                };
+                on_event(name, ev, &mut |ev| {
+                    self.publish(name, &ev);
+                    if let ProcessEvent::Exited(status) = ev {
+                        to_remove.push((name.to_string(), ctl.clone(), status))
+                    }
+                })
+            }
+        }
+
+        for (name, ctl, status) in to_remove {
+            self.retire(&name, &ctl, status);
+            statuses.insert(name, status);
+        }
+        true
+    }
+
+    /// The control of the managed process `name`.
+    fn control(
+        &self,
+        name: &str,
+    ) -> std::result::Result<Arc<RwLock<ProcessControl>>, ManagerError> {
+        match self.processes.read().unwrap().get(name) {
+            Some(ctl) => Ok(ctl.clone()),
+            None => Err(ManagerError::ProcessUnknown),
+        }
+    }
+
+    /// Every managed process by name, as the table holds them right now, so
+    /// that they can be gone through without keeping it locked.
+    fn snapshot(&self) -> Vec<(String, Arc<RwLock<ProcessControl>>)> {
+        self.processes
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, ctl)| (name.clone(), ctl.clone()))
+            .collect()
+    }
+
+    /// Remove a process whose exit has been delivered from the table, and
+    /// call the exit hook for it.
+    fn retire(&self, name: &str, ctl: &Arc<RwLock<ProcessControl>>, status: ExitStatus) {
+        // Only retire the entry we took the event from, in case the name was
+        // reused since.
+        let retired = {
+            let mut procs = self.processes.write().unwrap();
+            let retired = procs.get(name).is_some_and(|e| Arc::ptr_eq(e, ctl));
+            if retired {
+                procs.remove(name);
+            }
+            retired
+        };
+        if retired {
+            ctl.read().unwrap().event_queue.abandon();
+            self.retired
+                .write()
+                .unwrap()
+                .insert(name.to_string(), status);
+        }
+        if let Some(ExitHook(hook)) = self.config.exit_hook.as_ref().filter(|_| retired) {
+            hook(name, status);
+        }
+    }
+
+    /// Take the next queued event of any one managed process, if there is
+    /// one, without waiting. Events reach subscribers, and exited processes
+    /// are retired, as when the director delivers them. The processes are
+    /// scanned in no particular order.
+    pub fn try_recv_event(&self) -> Option<(String, ProcessEvent)> {
+        let processes = self.snapshot();
+        for (name, ctl) in processes {
+            let queue = ctl.read().unwrap().event_queue.clone();
+            if let Some(ev) = queue.pop() {
+                self.publish(&name, &ev.event);
+                if let ProcessEvent::Exited(status) = ev.event {
+                    self.retire(&name, &ctl, status);
+                }
+                return Some((name, ev.event));
+            }
+        }
+        None
+    }
+
+    /// Iterate over the events already queued by the managed processes, as
+    /// taken one at a time by `try_recv_event`, until none are left. This
+    /// never waits for more.
+    pub fn events(&self) -> impl Iterator<Item = (String, ProcessEvent)> + '_ {
+        std::iter::from_fn(move || self.try_recv_event())
+    }
+
+    /// Deliver every event already queued by the managed processes to
+    /// `on_event`, as the director would, in a single pass that does not
+    /// wait for any more. Processes whose exit is delivered are retired, and
+    /// their exit statuses returned by name.
+    pub fn drain<F>(&self, on_event: F) -> HashMap<String, ExitStatus>
+    where
+        F: Fn(ProcessEvent, &mut dyn FnMut(ProcessEvent)),
+    {
+        self.flush_events(|_name, ev, k| on_event(ev, k))
+    }
+
+    /// Return how each process that the director has retired so far ended
+    /// up, by name. Should a name have been reused, only the last process to
+    /// go by it is included.
+    pub fn outcomes(&self) -> HashMap<String, Outcome> {
+        self.retired
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, status)| (name.clone(), Outcome::of(status)))
+            .collect()
+    }
+
+    /// Whether any process that the director has retired so far exited
+    /// unsuccessfully, with a non-zero code or by a signal.
+    pub fn any_failed(&self) -> bool {
+        self.retired
+            .read()
+            .unwrap()
+            .values()
+            .any(|status| !status.success())
+    }
+
+    /// Like `drain`, but each event comes with the name of the process it
+    /// came from. The director does the same when it is told to return, so
+    /// that no output already read is lost.
+    pub fn flush_events<F>(&self, on_event: F) -> HashMap<String, ExitStatus>
+    where
+        F: Fn(&str, ProcessEvent, &mut dyn FnMut(ProcessEvent)),
+    {
+        let mut statuses = HashMap::new();
+        self.deliver_queued(
+            None,
+            &|name: &str, ev: TimedEvent, k: &mut dyn FnMut(ProcessEvent)| {
+                on_event(name, ev.event, k)
+            },
+            &mut statuses,
+        );
+        statuses
+    }
+
+    /// Wait until every managed process has exited and been retired, with
+    /// events delivered as by `run_director`, and return the exit status of
+    /// each by name.
+    pub fn join_all(&self) -> HashMap<String, ExitStatus> {
+        let mut statuses = HashMap::new();
+        let on_event = |_name: &str, ev: TimedEvent, k: &mut dyn FnMut(ProcessEvent)| k(ev.event);
+        while self.deliver_queued(None, &on_event, &mut statuses) {
+            self.config.clock.sleep(self.config.poll_interval);
+        }
+        statuses
+    }
+
+    /// Like `join_all`, but give up waiting once `timeout` has passed.
+    /// Returns the exit status of each process that finished by name, along
+    /// with the names of those still running, which are left alone.
+    pub fn wait_all_with_timeout(
+        &self,
+        timeout: time::Duration,
+    ) -> (HashMap<String, ExitStatus>, Vec<String>) {
+        let deadline = self.config.clock.now() + timeout;
+        let mut statuses = HashMap::new();
+        let on_event = |_name: &str, ev: TimedEvent, k: &mut dyn FnMut(ProcessEvent)| k(ev.event);
+        while self.deliver_queued(None, &on_event, &mut statuses)
+            && self.config.clock.now() < deadline
+        {
+            self.config.clock.sleep(self.config.poll_interval);
+        }
+        let mut running = self.list();
+        running.sort();
+        (statuses, running)
+    }
+
+    /// Return a channel on which the director also delivers every event it
+    /// passes on, along with the name of the process it came from. Each
+    /// subscriber receives its own copy of every event, and is forgotten once
+    /// its receiver is dropped.
+    pub fn subscribe(&self) -> Receiver<(String, ProcessEvent)> {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        self.subscribers.write().unwrap().push(sender);
+        receiver
+    }
+
+    /// Run the director on a thread of its own, delivering the events it
+    /// passes on, along with the name of the process each came from, as an
+    /// asynchronous stream. The stream ends once all processes have exited.
+    pub fn events_stream(&self) -> impl Stream<Item = (String, ProcessEvent)> {
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+        let manager = self.background_clone();
+        thread::spawn(move || {
+            manager.run_director_with_named_intercept(move |name, ev, k| {
+                // Once the stream is dropped there is nobody left to notify.
+                let _ = sender.unbounded_send((name.to_string(), ev.clone()));
+                k(ev)
+            })
+        });
+        receiver
+    }
+
+    fn publish(&self, name: &str, ev: &ProcessEvent) {
+        self.subscribers
+            .write()
+            .unwrap()
+            .retain(|subscriber| subscriber.send((name.to_string(), ev.clone())).is_ok());
+    }
+
+    /// Deliver queued events from every managed process to `on_event`, along
+    /// with the name of the process each came from, until all processes have
+    /// exited. Returns the exit status of each process by name.
+    pub fn run_director_with_named_intercept<F>(
+        &self,
+        on_event: F,
+    ) -> Result<HashMap<String, ExitStatus>>
+    where
+        F: Fn(&str, ProcessEvent, &mut dyn FnMut(ProcessEvent)),
+    {
+        self.direct(|| false, |name, ev, k| on_event(name, ev.event, k))
+    }
+
+    /// Like `run_director_with_named_intercept`, but each event comes with
+    /// the time at which it was produced, rather than delivered.
+    pub fn run_director_with_timed_intercept<F>(
+        &self,
+        on_event: F,
+    ) -> Result<HashMap<String, ExitStatus>>
+    where
+        F: Fn(&str, TimedEvent, &mut dyn FnMut(ProcessEvent)),
+    {
+        self.direct(|| false, on_event)
+    }
+
+    /// Like `run_director_with_named_intercept`, but `filter` returns the
+    /// event to deliver in place of the one it was given, possibly rewritten,
+    /// or `None` to drop it. Delivery, including the retirement of exited
+    /// processes, sees only what `filter` returns; dropping an `Exited` event
+    /// therefore leaves its process to be waited for forever.
+    pub fn run_director_with_filter<F>(&self, filter: F) -> Result<HashMap<String, ExitStatus>>
+    where
+        F: Fn(&str, ProcessEvent) -> Option<ProcessEvent>,
+    {
+        self.direct(
+            || false,
+            |name, ev, k| {
+                if let Some(ev) = filter(name, ev.event) {
+                    k(ev)
+                }
+            },
+        )
+    }
+
+    /// Like `run_director_with_named_intercept`, for callers that do not need
+    /// to know which process an event came from.
+    pub fn run_director_with_intercept<F>(&self, on_event: F) -> Result<HashMap<String, ExitStatus>>
+    where
+        F: Fn(ProcessEvent, &mut dyn FnMut(ProcessEvent)),
+    {
+        self.direct(|| false, |_name, ev, k| on_event(ev.event, k))
+    }
+
+    pub fn run_director(&self) -> Result<HashMap<String, ExitStatus>> {
+        self.run_director_with_intercept(|ev, k: &mut dyn FnMut(ProcessEvent)| k(ev))
+    }
+
+    /// Run the director until all processes have exited or `token` is
+    /// cancelled, whichever comes first. Processes still running on
+    /// cancellation are left alone; use `stop_all` to end them. The events
+    /// they queued by then are delivered before returning.
+    pub fn run_director_until(&self, token: CancelToken) -> Result<HashMap<String, ExitStatus>> {
+        self.direct(|| token.is_cancelled(), |_name, ev, k| k(ev.event))
+    }
+
+    /// Run the director until `token` is cancelled, or the manager closed,
+    /// even while there are no processes, so that it can be started before
+    /// any are spawned. The events queued by cancellation are delivered
+    /// before returning, and the exit status of each process retired
+    /// meanwhile is returned by name.
+    pub fn run_director_blocking_until_cancel(
+        &self,
+        token: CancelToken,
+    ) -> Result<HashMap<String, ExitStatus>> {
+        let mut statuses = HashMap::new();
+        let on_event = |_name: &str, ev: TimedEvent, k: &mut dyn FnMut(ProcessEvent)| k(ev.event);
+        while !token.is_cancelled() && !self.closed.is_cancelled() {
+            self.config.clock.sleep(self.config.poll_interval);
+            self.deliver_queued(Some(1), &on_event, &mut statuses);
+        }
+        self.deliver_queued(None, &on_event, &mut statuses);
+        Ok(statuses)
+    }
+
+    /// Run the director until all processes have exited or `timeout` has
+    /// elapsed, whichever comes first. Processes still running at the
+    /// deadline are left alone, and named in the outcome.
+    pub fn run_director_with_timeout(&self, timeout: time::Duration) -> Result<DirectorOutcome> {
+        let deadline = self.config.clock.now() + timeout;
+        let statuses = self.direct(
+            || self.config.clock.now() >= deadline,
+            |_name, ev, k| k(ev.event),
+        )?;
+        if self.is_empty() {
+            return Ok(DirectorOutcome::Finished(statuses));
+        }
+        let mut running = self.list();
+        running.sort();
+        Ok(DirectorOutcome::TimedOut { statuses, running })
+    }
+
+    /// Spawn `command` and record it in the process table under `name`.
+    fn start_process(
+        &self,
+        name: String,
+        command: &mut Command,
+    ) -> std::result::Result<Arc<RwLock<ProcessControl>>, ManagerError> {
+        self.start_process_with_output(name, command, &OutputTarget::Events, &OutputTarget::Events)
+    }
+
+    fn start_process_with_output(
+        &self,
+        name: String,
+        command: &mut Command,
+        stdout: &OutputTarget,
+        stderr: &OutputTarget,
+    ) -> std::result::Result<Arc<RwLock<ProcessControl>>, ManagerError> {
+        // Remember some details about `config`, since we will be moving it.
+        let name: String = name.to_string();
+
+        // Hold the table lock until the new process has been recorded, so
+        // that nothing else can claim the name in the meantime. If the name
+        // is already taken, report it before anything is spawned.
+        let mut processes = self.processes.write().unwrap();
+        if processes.contains_key(&name) {
+            return Err(ManagerError::NameConflict(name));
+        }
+
+        self.check_config()?;
+
+        self.prepare_command(command);
+
+        // Spawn the child process, which begins running immediately. The
+        // files its output goes to are opened first, so that failing to open
+        // one leaves nothing running.
+        let spawn_failed = |source: Error| ManagerError::SpawnFailed {
+            name: name.clone(),
+            source,
+        };
+        let stdin = self.stdin().map_err(spawn_failed)?;
+        let stdout_tee = stdout.tee().map_err(spawn_failed)?;
+        let stderr_tee = stderr.tee().map_err(spawn_failed)?;
+        let child = spawn_child(
+            command,
+            self.config.process_groups,
+            stdin,
+            stdout
+                .stdio(self.config.handles.contains(HandleType::StdOutput))
+                .map_err(spawn_failed)?,
+            stderr
+                .stdio(self.config.handles.contains(HandleType::StdError))
+                .map_err(spawn_failed)?,
+        )
+        .map_err(spawn_failed)?;
+        #[cfg(feature = "log")]
+        log::info!("spawned process {} (pid {})", name, child.id());
+        let mut ctl = self.new_control(name.clone(), child);
+        ctl.process_group = self.config.process_groups;
+        ctl.command_line = command_line(command);
+        #[cfg(windows)]
+        if let Err(source) = ctl.contain() {
+            ctl.kill(None).unwrap_or_default();
+            return Err(ManagerError::SpawnFailed { name, source });
+        }
+        ctl.stdout_tee = stdout_tee;
+        ctl.stderr_tee = stderr_tee;
+        let ctl = Arc::new(RwLock::new(ctl));
+
+        // Record the command in our "process table".
+        processes.insert(name, ctl.clone());
+        Ok(ctl)
+    }
+
+    /// Apply the manager-wide settings to `command` before it is spawned.
+    fn prepare_command(&self, command: &mut Command) {
+        // Apply the manager-wide defaults that `command` does not override.
+        for (key, value) in &self.config.default_env {
+            if !command.get_envs().any(|(k, _v)| k == key.as_str()) {
+                command.env(key, value);
+            }
+        }
+
+        // Clearing the environment also forgets the variables set on
+        // `command` and the defaults, so put those back afterwards.
+        if self.config.env_clear {
+            let envs: Vec<(OsString, OsString)> = command
+                .get_envs()
+                .filter_map(|(key, value)| Some((key.to_owned(), value?.to_owned())))
+                .collect();
+            command.env_clear().envs(envs);
+        }
+        if let Some(cwd) = &self.config.default_cwd {
+            if command.get_current_dir().is_none() {
+                command.current_dir(cwd);
+            }
+        }
+        #[cfg(unix)]
+        if let Some(PreExecHook(hook)) = &self.config.pre_exec {
+            let hook = hook.clone();
+            // The caller vouched for the hook when setting it.
+            unsafe {
+                command.pre_exec(move || hook());
+            }
+        }
+    }
+
+    /// Where the stdin of a new process comes from.
+    fn stdin(&self) -> Result<Stdio> {
+        match &self.config.stdin_file {
+            Some(path) => Ok(Stdio::from(fs::File::open(path)?)),
+            None => Ok(Stdio::piped()),
+        }
+    }
+
+    /// Reject settings that cannot work together.
+    fn check_config(&self) -> std::result::Result<(), ManagerError> {
+        if self.config.overflow_policy != OverflowPolicy::Block
+            && self.config.event_capacity.is_none()
+            && self.config.total_event_memory.is_none()
+        {
+            return Err(ManagerError::InvalidConfig(
+                "an overflow policy requires an event capacity or memory budget".to_string(),
+            ));
+        }
+        if self.config.output_rate_limit == Some(0) {
+            return Err(ManagerError::InvalidConfig(
+                "an output rate limit must allow some output".to_string(),
+            ));
+        }
+        #[cfg(not(unix))]
+        if self.config.pre_exec.is_some() {
+            return Err(ManagerError::InvalidConfig(
+                "pre-exec hooks are not supported on this platform".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// A fresh control for `child`, which is not yet monitored.
+    fn new_control(&self, name: String, child: Child) -> ProcessControl {
+        ProcessControl {
+            name,
+            child,
+            event_queue: EventQueue::new(
+                self.config.event_capacity,
+                self.config.overflow_policy,
+                self.config
+                    .total_event_memory
+                    .map(|limit| (limit, self.event_memory.clone())),
+                self.config.clock.clone(),
+            ),
+            stdout_line: Vec::new(),
+            stderr_line: Vec::new(),
+            monitor: None,
+            tags: HashSet::new(),
+            paused: false,
+            detached: false,
+            process_group: false,
+            #[cfg(windows)]
+            job: None,
+            stdout_tee: None,
+            stderr_tee: None,
+            history: OutputHistory::new(self.config.output_history),
+            stdin_buffer: VecDeque::new(),
+            closing_stdin: false,
+            stdout_escape: Vec::new(),
+            stderr_escape: Vec::new(),
+            stdout_text: Vec::new(),
+            stderr_text: Vec::new(),
+            stdout_mid_line: false,
+            stderr_mid_line: false,
+            #[cfg(feature = "encoding_rs")]
+            stdout_decoder: None,
+            #[cfg(feature = "encoding_rs")]
+            stderr_decoder: None,
+            started: self.config.clock.now(),
+            command_line: Vec::new(),
+            stdout_read: 0,
+            stderr_read: 0,
+            rate_limiter: self
+                .config
+                .output_rate_limit
+                .map(|rate| RateLimiter::new(rate, self.config.clock.now())),
+            clock: self.config.clock.clone(),
+            #[cfg(feature = "tracing")]
+            span: tracing::Span::none(),
+        }
+    }
+
+    /// Poll a started process for output and exit until it has finished,
+    /// passing every event through `on_event` into its event queue.
+    fn monitor_process<F>(
+        &self,
+        process: &Arc<RwLock<ProcessControl>>,
+        mut options: MonitorOptions,
+        on_event: F,
+    ) -> std::result::Result<(), ManagerError>
+    where
+        F: Fn(ProcessEvent, &dyn Fn(ProcessEvent) -> Result<()>) -> Result<()>,
+    {
+        let mut buf: Vec<u8> = vec![0; self.config.read_buffer_size];
+        let queue = process.read().unwrap().event_queue.clone();
+        #[cfg(feature = "tracing")]
+        let span = process.write().unwrap().span();
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+        let on_event = |ev: ProcessEvent| {
+            #[cfg(feature = "log")]
+            process.read().unwrap().log(&ev);
+            #[cfg(feature = "tracing")]
+            tracing::debug!(event = %ev, "process event");
+            if let Err(e) = (on_event)(ev, &|ev| {
+                queue.push(ev);
+                Ok(())
+            }) {
+                let ev = ProcessEvent::Error(ProcessError::ErrorHandling(e));
+                #[cfg(feature = "log")]
+                process.read().unwrap().log(&ev);
+                queue.push(ev)
+            };
+        };
+
+        let clock = &self.config.clock;
+        let mut attempts = 0;
+        let mut started = clock.now();
+        let mut timed_out = false;
+        let mut last_output = started;
+        let mut tripped = false;
+        let mut backoff = self.config.restart_backoff.map(|(initial, _max)| initial);
+        loop {
+            clock.sleep(self.config.poll_interval);
+
+            // Gather events while holding the lock, but deliver them only
+            // after releasing it: a full queue blocks delivery until the
+            // director catches up, and the director needs this lock to do so.
+            let mut events = Vec::new();
+            let mut ctl = process.write().unwrap();
+            if ctl.detached {
+                return Ok(());
+            }
+
+            // Pass on any input that the child's stdin would not take yet.
+            if let Err(e) = ctl.flush_stdin() {
+                events.push(ProcessEvent::Error(ProcessError::ErrorWriting(e)));
+            }
+
+            // Check whether there is output to be read.
+            for handle in &[HandleType::StdOutput, HandleType::StdError] {
+                if self.config.coalesce {
+                    events.extend(ctl.read_coalesced(*handle, &mut buf, clock.now()));
+                } else if let Some(ev) = ctl.read_limited(*handle, &mut buf, clock.now()) {
+                    events.push(ev)
+                }
+            }
+            // A paused process is not expected to write anything.
+            if ctl.paused
+                || events
+                    .iter()
+                    .any(|ev| matches!(ev, ProcessEvent::Output(_handle, _bytes)))
+            {
+                last_output = clock.now();
+            }
+
+            let status = match ctl.child.try_wait() {
+                Ok(None) => {
+                    if let Some(timeout) = options.timeout {
+                        if !timed_out && clock.now().duration_since(started) >= timeout {
+                            // The exit itself is noticed on a later poll.
+                            ctl.kill(self.config.kill_timeout).unwrap_or_default();
+                            events.push(ProcessEvent::TimedOut);
+                            timed_out = true;
+                        }
+                    }
+                    if let Some(idle) = self.config.watchdog {
+                        if !timed_out && !tripped && clock.now().duration_since(last_output) >= idle
+                        {
+                            ctl.kill(self.config.kill_timeout).unwrap_or_default();
+                            events.push(ProcessEvent::WatchdogTripped);
+                            tripped = true;
+                        }
+                    }
+                    None
+                }
+                Ok(Some(status)) => {
+                    // Deliver whatever the child wrote before exiting, so that
+                    // `Exited` is always the last event for a process.
+                    for handle in &[HandleType::StdOutput, HandleType::StdError] {
+                        while ctl.is_open(*handle) {
+                            match ctl.read_output(*handle, &mut buf) {
+                                Some(ev @ ProcessEvent::Error(_)) => {
+                                    events.push(ev);
+                                    break;
+                                }
+                                Some(ev) => events.push(ev),
+                                None => break,
+                            }
+                        }
+                    }
+                    Some(Ok(status))
+                }
+                Err(e) => Some(Err(e)),
+            };
+
+            // Copy output to any tee files as it was written, and to the
+            // history before it is relabelled or split, reporting failures to
+            // write the files as errors after the output itself.
+            let mut tee_results: Vec<Result<()>> = events.iter().map(|ev| ctl.tee(ev)).collect();
+            if let Some(Ok(_status)) = &status {
+                tee_results.push(ctl.flush_tees());
+            }
+            if self.config.strip_ansi {
+                events = events
+                    .into_iter()
+                    .flat_map(|ev| ctl.strip_ansi(ev))
+                    .collect();
+            }
+            for ev in &events {
+                ctl.history.record(ev);
+            }
+            events.extend(
+                tee_results
+                    .into_iter()
+                    .filter_map(|result| result.err())
+                    .map(|e| ProcessEvent::Error(ProcessError::ErrorHandling(e))),
+            );
+
+            if self.config.merged_output {
+                for ev in &mut events {
+                    if let ProcessEvent::Output(handle, _bytes) = ev {
+                        *handle = HandleType::StdOutput;
+                    }
+                }
+            }
+            if self.config.line_buffering {
+                events = events
+                    .into_iter()
+                    .flat_map(|ev| ctl.split_lines(ev))
+                    .collect();
+            }
+            if let Some(encoding) = self.config.text_output {
+                events = events
+                    .into_iter()
+                    .flat_map(|ev| ctl.decode_text(ev, encoding))
+                    .collect();
+            }
+            if self.config.name_prefix {
+                events = events.into_iter().map(|ev| ctl.prefix_name(ev)).collect();
+            }
+            drop(ctl);
+            for ev in events {
+                (on_event)(ev)
+            }
+
+            let status = match status {
+                None => continue,
+                Some(Ok(status)) => status,
+                Some(Err(e)) => {
+                    (on_event)(ProcessEvent::Error(ProcessError::ErrorWaiting(e)));
+                    return Ok(());
+                }
+            };
+
+            // `try_wait` reaped the child when it returned its status, so by
+            // now it cannot linger as a zombie, and any replacement is only
+            // spawned once it is gone.
+            if let Some((restart, should_restart)) = &mut options.restart {
+                if should_restart(status, attempts) {
+                    let mut delay = time::Duration::from_secs(0);
+                    if let (Some(wait), Some((initial, max))) =
+                        (&mut backoff, self.config.restart_backoff)
+                    {
+                        if clock.now().duration_since(started) > max {
+                            *wait = initial;
+                        }
+                        delay = *wait;
+                        *wait = std::cmp::min(*wait * 2, max);
+                    }
+                    clock.sleep(delay);
+                    if process.read().unwrap().detached {
+                        return Ok(());
+                    }
+
+                    let mut built;
+                    let command = match restart {
+                        Restart::Command(command) => &mut **command,
+                        Restart::Factory(factory) => {
+                            built = factory(attempts + 1);
+                            self.prepare_command(&mut built);
+                            &mut built
+                        }
+                    };
+                    let spawned = self.stdin().and_then(|stdin| {
+                        spawn_child(
+                            command,
+                            self.config.process_groups,
+                            stdin,
+                            OutputTarget::Events
+                                .stdio(self.config.handles.contains(HandleType::StdOutput))?,
+                            OutputTarget::Events
+                                .stdio(self.config.handles.contains(HandleType::StdError))?,
+                        )
+                    });
+                    match spawned {
+                        Ok(child) => {
+                            attempts += 1;
+                            started = clock.now();
+                            timed_out = false;
+                            last_output = started;
+                            tripped = false;
+                            #[cfg(windows)]
+                            let contained;
+                            {
+                                let mut ctl = process.write().unwrap();
+                                ctl.child = child;
+                                ctl.command_line = command_line(command);
+                                #[cfg(windows)]
+                                {
+                                    contained = ctl.contain();
+                                }
+                                ctl.started = clock.now();
+                                ctl.paused = false;
+                                ctl.stdin_buffer.clear();
+                                ctl.closing_stdin = false;
+                                #[cfg(feature = "tracing")]
+                                span.record("pid", ctl.child.id());
+                            }
+                            (on_event)(ProcessEvent::Restarted {
+                                attempt: attempts,
+                                delay,
+                            });
+                            // The new child keeps running, though outside
+                            // of any job.
+                            #[cfg(windows)]
+                            if let Err(e) = contained {
+                                (on_event)(ProcessEvent::Error(ProcessError::ErrorRestarting(e)));
+                            }
+                            continue;
+                        }
+                        Err(e) => (on_event)(ProcessEvent::Error(ProcessError::ErrorRestarting(e))),
+                    }
+                }
+            }
+            for ev in ProcessEvent::exit_events(status) {
+                (on_event)(ev)
+            }
+            return Ok(());
+        }
+    }
+
+    pub fn run_process_with_intercept<F>(
+        &self,
+        name: String,
+        command: &mut Command,
+        on_event: F,
+    ) -> std::result::Result<(), ManagerError>
+    where
+        F: Fn(ProcessEvent, &dyn Fn(ProcessEvent) -> Result<()>) -> Result<()>,
+    {
+        let ctl = self.start_process(name, command)?;
+        self.monitor_process(&ctl, Default::default(), on_event)
+    }
+
+    /// Like `run_process_with_intercept`, but whenever the process exits and
+    /// `policy` permits it, `command` is spawned again under the same name
+    /// and a `Restarted` event is reported in place of `Exited`.
+    pub fn run_process_with_policy<F>(
+        &self,
+        name: String,
+        command: &mut Command,
+        policy: RestartPolicy,
+        on_event: F,
+    ) -> std::result::Result<(), ManagerError>
+    where
+        F: Fn(ProcessEvent, &dyn Fn(ProcessEvent) -> Result<()>) -> Result<()>,
+    {
+        let ctl = self.start_process(name, command)?;
+        self.monitor_process(
+            &ctl,
+            MonitorOptions {
+                restart: Some((Restart::Command(command), &|status, attempts| {
+                    policy.permits(status, attempts)
+                })),
+                ..Default::default()
+            },
+            on_event,
+        )
+    }
+
+    /// Like `run_process_with_policy`, but whether to restart the process
+    /// after each exit is up to `should_restart`, given the exit status and
+    /// the number of restarts so far. Unlike the policies, it can restart a
+    /// process that succeeded as well as one that failed.
+    pub fn run_process_with_predicate<P, F>(
+        &self,
+        name: String,
+        command: &mut Command,
+        should_restart: P,
+        on_event: F,
+    ) -> std::result::Result<(), ManagerError>
+    where
+        P: Fn(ExitStatus, u32) -> bool,
+        F: Fn(ProcessEvent, &dyn Fn(ProcessEvent) -> Result<()>) -> Result<()>,
+    {
+        let ctl = self.start_process(name, command)?;
+        self.monitor_process(
+            &ctl,
+            MonitorOptions {
+                restart: Some((Restart::Command(command), &should_restart)),
+                ..Default::default()
+            },
+            on_event,
+        )
+    }
+
+    /// Like `run_process_with_policy`, but each run of the process gets a
+    /// command of its own, built by `factory` from the attempt number, which
+    /// is 0 for the first run and counts the restarts after that.
+    pub fn run_process_with_factory<C, F>(
+        &self,
+        name: String,
+        factory: C,
+        policy: RestartPolicy,
+        on_event: F,
+    ) -> std::result::Result<(), ManagerError>
+    where
+        C: Fn(u32) -> Command,
+        F: Fn(ProcessEvent, &dyn Fn(ProcessEvent) -> Result<()>) -> Result<()>,
+    {
+        let ctl = self.start_process(name, &mut factory(0))?;
+        self.monitor_process(
+            &ctl,
+            MonitorOptions {
+                restart: Some((Restart::Factory(&factory), &|status, attempts| {
+                    policy.permits(status, attempts)
+                })),
+                ..Default::default()
+            },
+            on_event,
+        )
+    }
+
+    /// Like `run_process_with_intercept`, but the process is killed if it is
+    /// still running once `timeout` has passed. A `TimedOut` event is then
+    /// reported, followed by the usual `Exited`.
+    pub fn run_process_with_timeout<F>(
+        &self,
+        name: String,
+        command: &mut Command,
+        timeout: time::Duration,
+        on_event: F,
+    ) -> std::result::Result<(), ManagerError>
+    where
+        F: Fn(ProcessEvent, &dyn Fn(ProcessEvent) -> Result<()>) -> Result<()>,
+    {
+        let ctl = self.start_process(name, command)?;
+        self.monitor_process(
+            &ctl,
+            MonitorOptions {
+                timeout: Some(timeout),
+                ..Default::default()
+            },
+            on_event,
+        )
+    }
+
+    /// Like `run_process_with_intercept`, but the process's stdout and stderr
+    /// go where `stdout` and `stderr` say. A handle sent to a file is never
+    /// read, so no `Output` or `Eof` events are reported for it.
+    pub fn run_process_with_output<F>(
+        &self,
+        name: String,
+        command: &mut Command,
+        stdout: OutputTarget,
+        stderr: OutputTarget,
+        on_event: F,
+    ) -> std::result::Result<(), ManagerError>
+    where
+        F: Fn(ProcessEvent, &dyn Fn(ProcessEvent) -> Result<()>) -> Result<()>,
+    {
+        let ctl = self.start_process_with_output(name, command, &stdout, &stderr)?;
+        self.monitor_process(&ctl, Default::default(), on_event)
+    }
+
+    /// Like `run_process_with_intercept`, but the process is held to
+    /// `limits`, which are set in the child before `command` is executed.
+    /// Only Unix is supported.
+    pub fn run_process_with_limits<F>(
+        &self,
+        name: String,
+        command: &mut Command,
+        limits: ResourceLimits,
+        on_event: F,
+    ) -> std::result::Result<(), ManagerError>
+    where
+        F: Fn(ProcessEvent, &dyn Fn(ProcessEvent) -> Result<()>) -> Result<()>,
+    {
+        #[cfg(unix)]
+        unsafe {
+            command.pre_exec(move || limits.apply());
+        }
+        #[cfg(not(unix))]
+        let _ = (name, command, limits, on_event);
+        #[cfg(not(unix))]
+        return Err(ManagerError::Io(Error::new(
+            ErrorKind::Unsupported,
+            "resource limits are not supported on this platform",
+        )));
+        #[cfg(unix)]
+        self.run_process_with_intercept(name, command, on_event)
+    }
+
+    pub fn run_process(
+        &self,
+        name: String,
+        command: &mut Command,
+    ) -> std::result::Result<(), ManagerError> {
+        self.run_process_with_intercept(
+            name,
+            command,
+            |ev, k: &dyn Fn(ProcessEvent) -> Result<()>| k(ev),
+        )
+    }
+
+    /// Start `command` under `name` and return immediately, monitoring the
+    /// process on a thread of its own. Only the director then needs to be run
+    /// by the caller.
+    pub fn spawn(
+        &self,
+        name: String,
+        command: &mut Command,
+    ) -> std::result::Result<(), ManagerError> {
+        self.spawn_with_tags(name, command, HashSet::new())
+    }
+
+    /// Like `spawn`, but run `cmdline` with the platform's shell: `sh -c` on
+    /// Unix, and `cmd /C` on Windows.
+    pub fn spawn_shell(
+        &self,
+        name: String,
+        cmdline: &str,
+    ) -> std::result::Result<(), ManagerError> {
+        #[cfg(unix)]
+        let mut command = Command::new("sh");
+        #[cfg(unix)]
+        command.arg("-c");
+        #[cfg(not(unix))]
+        let mut command = Command::new("cmd");
+        #[cfg(not(unix))]
+        command.arg("/C");
+        command.arg(cmdline);
+        self.spawn(name, &mut command)
+    }
+
+    /// Like `spawn`, but the process is also labelled with `tags`, so that it
+    /// can be found with `list_by_tag` and stopped with `stop_by_tag`.
+    pub fn spawn_with_tags(
+        &self,
+        name: String,
+        command: &mut Command,
+        tags: HashSet<String>,
+    ) -> std::result::Result<(), ManagerError> {
+        let ctl = self.start_process(name, command)?;
+        ctl.write().unwrap().tags = tags;
+        self.monitor_in_background(&ctl);
+        Ok(())
+    }
+
+    /// Like `spawn`, but return a handle through which the process can be
+    /// reached without repeating its name.
+    pub fn spawn_handle(
+        &self,
+        name: String,
+        command: &mut Command,
+    ) -> std::result::Result<ProcessHandle, ManagerError> {
+        self.spawn(name.clone(), command)?;
+        Ok(ProcessHandle {
+            manager: self.clone(),
+            name,
+        })
+    }
+
+    /// Spawn `count` processes like `spawn`, named `base_name-0` up to
+    /// `base_name-{count - 1}`, each running the command that `factory`
+    /// builds for its index. Returns the names in order. If one fails to
+    /// spawn, those spawned before it are left running.
+    pub fn spawn_replicas<F>(
+        &self,
+        base_name: &str,
+        count: usize,
+        factory: F,
+    ) -> std::result::Result<Vec<String>, ManagerError>
+    where
+        F: Fn(usize) -> Command,
+    {
+        (0..count)
+            .map(|index| {
+                let name = format!("{}-{}", base_name, index);
+                self.spawn(name.clone(), &mut factory(index))?;
+                Ok(name)
+            })
+            .collect()
+    }
+
+    /// Take over monitoring `child`, which was spawned elsewhere, under
+    /// `name` as though it had been started with `spawn`. Output is reported
+    /// only for those of its stdout and stderr that were piped, and it is not
+    /// restarted. Its pipes are made non-blocking.
+    pub fn adopt(&self, name: String, child: Child) -> std::result::Result<(), ManagerError> {
+        let mut processes = self.processes.write().unwrap();
+        if processes.contains_key(&name) {
+            return Err(ManagerError::NameConflict(name));
+        }
+        self.check_config()?;
+        if let Some(h) = &child.stdin {
+            set_nonblocking(h)?;
+        }
+        if let Some(h) = &child.stdout {
+            set_nonblocking(h)?;
+        }
+        if let Some(h) = &child.stderr {
+            set_nonblocking(h)?;
+        }
+        #[cfg(feature = "log")]
+        log::info!("adopted process {} (pid {})", name, child.id());
+        let ctl = Arc::new(RwLock::new(self.new_control(name.clone(), child)));
+        processes.insert(name, ctl.clone());
+        drop(processes);
+
+        self.monitor_in_background(&ctl);
+        Ok(())
+    }
+
+    /// Monitor a started process on a thread of its own.
+    fn monitor_in_background(&self, ctl: &Arc<RwLock<ProcessControl>>) {
+        let manager = self.background_clone();
+        let monitored = ctl.clone();
+        let monitor = thread::spawn(move || {
+            manager.monitor_process(
+                &monitored,
+                Default::default(),
+                |ev, k: &dyn Fn(ProcessEvent) -> Result<()>| k(ev),
+            )
+        });
+        ctl.write().unwrap().monitor = Some(monitor);
+    }
+
+    /// Start `command` under `name` and monitor it in the background like
+    /// `spawn`, passing its events through `on_event`, but return only once a
+    /// line of its stdout matches `pattern`. It is an error for the process
+    /// to exit first, or for the ready timeout, if any, to elapse first; in
+    /// the latter case the process is left running.
+    pub fn run_process_until_ready<F>(
+        &self,
+        name: String,
+        command: &mut Command,
+        pattern: Regex,
+        on_event: F,
+    ) -> std::result::Result<(), ManagerError>
+    where
+        F: Fn(ProcessEvent, &dyn Fn(ProcessEvent) -> Result<()>) -> Result<()> + Send + 'static,
+    {
+        let (ready_tx, ready_rx) = crossbeam_channel::bounded(1);
+        let partial = RefCell::new(Vec::new());
+        let scanning = Cell::new(true);
+        let matches = move |line: &[u8]| pattern.is_match(&String::from_utf8_lossy(line));
+        let on_event = move |ev: ProcessEvent, k: &dyn Fn(ProcessEvent) -> Result<()>| {
+            if scanning.get() {
+                let chunk = match &ev {
+                    ProcessEvent::Output(HandleType::StdOutput, bytes) => Some(bytes.as_slice()),
+                    ProcessEvent::Text(HandleType::StdOutput, text) => Some(text.as_bytes()),
+                    _ => None,
+                };
+                let ready = match (&ev, chunk) {
+                    (_ev, Some(bytes)) => {
+                        let mut partial = partial.borrow_mut();
+                        partial.extend_from_slice(bytes);
+                        let end = partial
+                            .iter()
+                            .rposition(|&b| b == b'\n')
+                            .map_or(0, |i| i + 1);
+                        let ready =
+                            end > 0 && partial[..end - 1].split(|&b| b == b'\n').any(&matches);
+                        partial.drain(..end);
+                        ready.then_some(true)
+                    }
+                    (ProcessEvent::Line(HandleType::StdOutput, line), None) => {
+                        matches(line.as_bytes()).then_some(true)
+                    }
+                    (ProcessEvent::Exited(_status), None) => Some(false),
+                    _ => None,
+                };
+                if let Some(ready) = ready {
+                    scanning.set(false);
+                    let _ = ready_tx.try_send(ready);
+                }
+            }
+            (on_event)(ev, k)
+        };
+
+        let ctl = self.start_process(name, command)?;
+        let manager = self.background_clone();
+        let monitored = ctl.clone();
+        let monitor = thread::spawn(move || {
+            manager.monitor_process(&monitored, Default::default(), on_event)
+        });
+        ctl.write().unwrap().monitor = Some(monitor);
+
+        let ready = match self.config.ready_timeout {
+            Some(timeout) => ready_rx.recv_timeout(timeout).map_err(|e| e.is_timeout()),
+            None => ready_rx.recv().map_err(|_| false),
+        };
+        match ready {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(ManagerError::NotReady("process exited".to_string())),
+            Err(true) => Err(ManagerError::NotReady("timed out".to_string())),
+            Err(false) => Err(ManagerError::NotReady("monitoring stopped".to_string())),
+        }
+    }
+
+    /// Start each of `specs` in the background like `spawn`, but only after
+    /// its dependencies are ready, waiting for readiness as
+    /// `run_process_until_ready` does. A dependency may also name a process
+    /// that is already managed. If two specs share a name, or the
+    /// dependencies form a cycle or name an unknown process, nothing is
+    /// started. If a process fails to start or
+    /// become ready, those started before it are left running.
+    pub fn start_ordered(&self, specs: Vec<ProcessSpec>) -> std::result::Result<(), ManagerError> {
+        let mut names: HashSet<String> = HashSet::new();
+        for spec in &specs {
+            if !names.insert(spec.name.clone()) {
+                return Err(ManagerError::InvalidConfig(format!(
+                    "{} is given more than once",
+                    spec.name
+                )));
+            }
+        }
+        for spec in &specs {
+            for dep in &spec.depends_on {
+                if !names.contains(dep.as_str())
+                    && !self.processes.read().unwrap().contains_key(dep)
+                {
+                    return Err(ManagerError::InvalidConfig(format!(
+                        "{} depends on unknown process {}",
+                        spec.name, dep
+                    )));
+                }
+            }
+        }
+
+        // Repeatedly take the first spec whose dependencies have all been
+        // taken, so that independent specs keep their given order.
+        let mut pending = specs;
+        let mut ordered = Vec::new();
+        let mut started: HashSet<String> = HashSet::new();
+        while !pending.is_empty() {
+            let next = pending.iter().position(|spec| {
+                spec.depends_on
+                    .iter()
+                    .all(|dep| started.contains(dep) || !names.contains(dep))
+            });
+            match next {
+                Some(i) => {
+                    let spec = pending.remove(i);
+                    started.insert(spec.name.clone());
+                    ordered.push(spec);
+                }
+                None => {
+                    let mut cycle: Vec<&str> =
+                        pending.iter().map(|spec| spec.name.as_str()).collect();
+                    cycle.sort();
+                    return Err(ManagerError::InvalidConfig(format!(
+                        "dependency cycle among {}",
+                        cycle.join(", ")
+                    )));
+                }
+            }
+        }
+
+        for mut spec in ordered {
+            match spec.ready {
+                Some(pattern) => self.run_process_until_ready(
+                    spec.name,
+                    &mut spec.command,
+                    pattern,
+                    |ev, k: &dyn Fn(ProcessEvent) -> Result<()>| k(ev),
+                )?,
+                None => self.spawn(spec.name, &mut spec.command)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Start `command` as a replacement for the process called `name`, and
+    /// once a line of its stdout matches `readiness`, as with
+    /// `run_process_until_ready`, swap it in under `name` and kill the old
+    /// process. Until then the replacement runs under a temporary name, and
+    /// `name` refers to the old process throughout; from the swap on, its
+    /// span, log lines and name prefix use `name`. If the replacement does
+    /// not become ready, it is killed and the old process kept.
+    pub fn replace_process(
+        &self,
+        name: &str,
+        command: &mut Command,
+        readiness: Regex,
+    ) -> std::result::Result<(), ManagerError> {
+        if !self.processes.read().unwrap().contains_key(name) {
+            return Err(ManagerError::ProcessUnknown);
+        }
+        let temporary = format!("{} (replacement)", name);
+        if let Err(e) = self.run_process_until_ready(
+            temporary.clone(),
+            command,
+            readiness,
+            |ev, k: &dyn Fn(ProcessEvent) -> Result<()>| k(ev),
+        ) {
+            let replacement = self.processes.write().unwrap().remove(&temporary);
+            if let Some(ctl) = replacement {
+                let mut ctl = ctl.write().unwrap();
+                ctl.event_queue.abandon();
+                ctl.kill(self.config.kill_timeout)?;
+            }
+            return Err(e);
+        }
+
+        let old = {
+            let mut procs = self.processes.write().unwrap();
+            let ctl = match procs.remove(&temporary) {
+                Some(ctl) => ctl,
+                None => return Err(ManagerError::NotReady("process exited".to_string())),
+            };
+            ctl.write().unwrap().rename(name);
+            procs.insert(name.to_string(), ctl)
+        };
+        if let Some(ctl) = old {
+            let mut ctl = ctl.write().unwrap();
+            ctl.event_queue.abandon();
+            ctl.kill(self.config.kill_timeout)?;
+        }
+        Ok(())
+    }
+
+    /// Kill the process called `name`, if there is one, and start `command`
+    /// in its place under the same name.
+    pub fn restart_process(
+        &self,
+        name: &str,
+        command: &mut Command,
+    ) -> std::result::Result<(), ManagerError> {
+        let old = self.processes.write().unwrap().remove(name);
+        if let Some(ctl) = old {
+            let mut ctl = ctl.write().unwrap();
+            ctl.event_queue.abandon();
+            ctl.kill(self.config.kill_timeout)?;
+        }
+        self.spawn(name.to_string(), command)
+    }
+
+    /// Stop managing the process called `name` without killing it, and hand
+    /// its `Child` to the caller, along with its stdin and any stdout and
+    /// stderr pipes that have not reached end-of-file. Those pipes are in
+    /// non-blocking mode, and any input still buffered for stdin is
+    /// discarded. Waits for its monitoring to stop first.
+    pub fn detach(&mut self, name: &str) -> std::result::Result<Child, ManagerError> {
+        let mut ctl = match self.processes.write().unwrap().remove(name) {
+            Some(ctl) => ctl,
+            None => return Err(ManagerError::ProcessUnknown),
+        };
+        let monitor = {
+            let mut ctl = ctl.write().unwrap();
+            ctl.detached = true;
+            ctl.event_queue.abandon();
+            ctl.monitor.take()
+        };
+        if let Some(monitor) = monitor {
+            let _ = monitor.join();
+        }
+
+        // A monitoring loop running on another thread, or the director, may
+        // hold on to the control until its next poll.
+        loop {
+            match Arc::try_unwrap(ctl) {
+                Ok(ctl) => return Ok(ctl.into_inner().unwrap().child),
+                Err(shared) => ctl = shared,
+            }
+            self.config.clock.sleep(self.config.poll_interval);
+        }
+    }
+
+    /// Return the names of all managed processes, in no particular order.
+    pub fn list(&self) -> Vec<String> {
+        self.processes.read().unwrap().keys().cloned().collect()
+    }
+
+    /// Return the number of managed processes.
+    pub fn len(&self) -> usize {
+        self.processes.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.processes.read().unwrap().is_empty()
+    }
+
+    /// Return the names of all managed processes labelled with `tag`, in no
+    /// particular order.
+    pub fn list_by_tag(&self, tag: &str) -> Vec<String> {
+        self.processes
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, ctl)| ctl.read().unwrap().tags.contains(tag))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Check whether the process called `name` is still running, without
+    /// waiting for it.
+    pub fn status(&self, name: &str) -> std::result::Result<ProcessStatus, ManagerError> {
+        let ctl = self.control(name)?;
+        let status = match ctl.write().unwrap().child.try_wait() {
+            Ok(None) => ProcessStatus::Running,
+            Ok(Some(status)) => ProcessStatus::Exited(status),
+            Err(_) => ProcessStatus::Unknown,
+        };
+        Ok(status)
+    }
+
+    /// Sample the memory and CPU time used so far by the managed process
+    /// `name`. Only Linux is supported for now. A process that has already
+    /// exited is reported as `ProcessUnknown`.
+    pub fn resource_usage(&self, name: &str) -> std::result::Result<ResourceUsage, ManagerError> {
+        let ctl = self.control(name)?;
+
+        // Hold the lock while reading, so that the child cannot be reaped
+        // and its pid reused by another process in the meantime.
+        let mut ctl = ctl.write().unwrap();
+        if ctl.child.try_wait()?.is_some() {
+            return Err(ManagerError::ProcessUnknown);
+        }
+        match read_resource_usage(ctl.child.id()) {
+            Ok(usage) => Ok(usage),
+            Err(e) if e.kind() == ErrorKind::NotFound => Err(ManagerError::ProcessUnknown),
+            Err(e) => Err(ManagerError::Io(e)),
+        }
+    }
+
+    /// Return the most recent lines of output of the managed process `name`,
+    /// oldest first, as kept according to `with_output_history`.
+    pub fn recent_output(&self, name: &str) -> std::result::Result<Vec<String>, ManagerError> {
+        let ctl = self.control(name)?;
+        let lines = ctl.read().unwrap().history.lines.iter().cloned().collect();
+        Ok(lines)
+    }
+
+    /// Return how long ago the managed process `name` was spawned, or last
+    /// restarted.
+    pub fn uptime(&self, name: &str) -> std::result::Result<time::Duration, ManagerError> {
+        let started = self.control(name)?.read().unwrap().started;
+        Ok(self.config.clock.now().duration_since(started))
+    }
+
+    /// Return the program and arguments that the managed process `name` was
+    /// last started with. It is empty for an adopted process.
+    pub fn command_line(&self, name: &str) -> std::result::Result<Vec<String>, ManagerError> {
+        let command_line = self.control(name)?.read().unwrap().command_line.clone();
+        Ok(command_line)
+    }
+
+    /// Return how many bytes have been read from the stdout and stderr of the
+    /// managed process `name`, in that order, counting those of the
+    /// processes it replaced on restarts.
+    pub fn bytes_read(&self, name: &str) -> std::result::Result<(u64, u64), ManagerError> {
+        let ctl = self.control(name)?;
+        let ctl = ctl.read().unwrap();
+        Ok((ctl.stdout_read, ctl.stderr_read))
+    }
+
+    /// Return the OS process id of the managed process called `name`.
+    pub fn pid(&self, name: &str) -> std::result::Result<u32, ManagerError> {
+        let pid = self.control(name)?.read().unwrap().child.id();
+        Ok(pid)
+    }
+
+    /// Block until the managed process `name` exits, polling it at the
+    /// configured interval, and return its exit status.
+    pub fn wait_for_process(&self, name: &str) -> std::result::Result<ExitStatus, ManagerError> {
+        let ctl = self.control(name)?;
+
+        loop {
+            if let Some(status) = ctl.write().unwrap().child.try_wait()? {
+                return Ok(status);
+            }
+            self.config.clock.sleep(self.config.poll_interval);
+        }
+    }
+
+    /// Send the Unix signal `signal` to the managed process `name`. Signals
+    /// are not supported on other platforms.
+    pub fn send_signal(&self, name: &str, signal: i32) -> std::result::Result<(), ManagerError> {
+        let ctl = self.control(name)?;
+
+        let result = ctl.write().unwrap().signal(signal);
+        result
+    }
+
+    /// Freeze the managed process `name` with SIGSTOP until it is resumed.
+    pub fn pause(&self, name: &str) -> std::result::Result<(), ManagerError> {
+        self.set_paused(name, true)
+    }
+
+    /// Thaw the managed process `name` with SIGCONT after a `pause`.
+    pub fn resume(&self, name: &str) -> std::result::Result<(), ManagerError> {
+        self.set_paused(name, false)
+    }
+
+    fn set_paused(&self, name: &str, paused: bool) -> std::result::Result<(), ManagerError> {
+        let ctl = self.control(name)?;
+
+        let mut ctl = ctl.write().unwrap();
+        if ctl.paused == paused {
+            return Ok(());
+        }
+        #[cfg(unix)]
+        ctl.signal(if paused { libc::SIGSTOP } else { libc::SIGCONT })?;
+        #[cfg(not(unix))]
+        ctl.signal(0)?;
+
+        ctl.paused = paused;
+        let queue = ctl.event_queue.clone();
+        // A full queue blocks until the director catches up, and the director
+        // needs this lock to do so.
+        drop(ctl);
+        queue.push(if paused {
+            ProcessEvent::Paused
+        } else {
+            ProcessEvent::Resumed
+        });
+        Ok(())
+    }
+
+    /// Forward SIGINT and SIGTERM, when this process receives them, to every
+    /// managed process, so that they are not orphaned by Ctrl-C. The handlers
+    /// are chained after any already installed, and replace the default of
+    /// terminating this process: the director winds down once the children
+    /// have exited. Calling this more than once has no further effect.
+    #[cfg(unix)]
+    pub fn install_signal_forwarding(&self) -> std::result::Result<(), ManagerError> {
+        if self.forwarding_signals.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let mut signals = match signal_hook::iterator::Signals::new([libc::SIGINT, libc::SIGTERM]) {
+            Ok(signals) => signals,
+            Err(e) => {
+                self.forwarding_signals.store(false, Ordering::SeqCst);
+                return Err(e.into());
+            }
+        };
+        let processes = self.processes.clone();
+        thread::spawn(move || {
+            for signal in signals.forever() {
+                let controls: Vec<_> = processes.read().unwrap().values().cloned().collect();
+                for ctl in controls {
+                    // A process that has already exited has nothing to forward to.
+                    let _ = ctl.write().unwrap().signal(signal);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Write `data` to the standard input of the managed process `name`.
+    /// Whatever the pipe does not take at once is buffered, and written by
+    /// the monitoring loop as the child reads its input, so this never
+    /// blocks. For a process run with `spawn_async`, which has no monitoring
+    /// loop, the buffer is only written out when more input is sent.
+    pub fn send_input(&self, name: &str, data: &[u8]) -> std::result::Result<(), ManagerError> {
+        let ctl = self.control(name)?;
+        if self.config.stdin_file.is_some() {
+            return Err(ManagerError::InvalidConfig(
+                "stdin is read from a file".to_string(),
+            ));
+        }
+        let mut ctl = ctl.write().unwrap();
+        if ctl.child.stdin.is_none() || ctl.closing_stdin {
+            return Err(ManagerError::StdinClosed);
+        }
+        ctl.stdin_buffer.extend(data);
+        ctl.flush_stdin()?;
+        Ok(())
+    }
+
+    /// Write `line` and a newline to the standard input of the managed
+    /// process `name`.
+    pub fn send_line(&self, name: &str, line: &str) -> std::result::Result<(), ManagerError> {
+        self.send_input(name, format!("{}\n", line).as_bytes())
+    }
+
+    /// Close the standard input of the managed process `name`, so that it
+    /// reads end-of-file once it has read what was already sent. Closing it
+    /// again does nothing.
+    pub fn close_stdin(&self, name: &str) -> std::result::Result<(), ManagerError> {
+        let ctl = self.control(name)?;
+        let mut ctl = ctl.write().unwrap();
+        ctl.closing_stdin = true;
+        ctl.flush_stdin()?;
+        Ok(())
+    }
+
+    /// Ask the process called `name` to terminate, and kill it if it has not
+    /// exited within `grace`. On Unix the request is a SIGTERM; on other
+    /// platforms there is no such request, and the process is killed at once.
+    pub fn stop_process_graceful(
+        &self,
+        name: &str,
+        grace: time::Duration,
+    ) -> std::result::Result<StopOutcome, ManagerError> {
+        let ctl = match self.processes.write().unwrap().remove(name) {
+            Some(ctl) => ctl,
+            None => return Err(ManagerError::ProcessUnknown),
+        };
+        ctl.read().unwrap().event_queue.abandon();
+
+        #[cfg(unix)]
+        {
+            // Check for an earlier exit while holding the lock, so that the
+            // pid cannot have been reaped and reused by the time we signal.
+            {
+                let mut ctl = ctl.write().unwrap();
+                if ctl.child.try_wait()?.is_some() {
+                    return Ok(StopOutcome::Graceful);
+                }
+                unsafe {
+                    libc::kill(ctl.signal_target(), libc::SIGTERM);
+                }
+            }
+
+            let clock = &self.config.clock;
+            let deadline = clock.now() + grace;
+            while clock.now() < deadline {
+                if ctl.write().unwrap().child.try_wait()?.is_some() {
+                    return Ok(StopOutcome::Graceful);
+                }
+                clock.sleep(time::Duration::from_millis(10));
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = grace;
+
+        ctl.write().unwrap().kill(self.config.kill_timeout)?;
+        Ok(StopOutcome::Killed)
+    }
+
+    /// Kill every managed process for which `predicate` holds, returning the
+    /// outcome for each by name.
+    fn stop_where<P>(&self, predicate: P) -> Vec<(String, std::result::Result<(), ManagerError>)>
+    where
+        P: Fn(&ProcessControl) -> bool,
+    {
+        // Take the processes out of the table under a single lock, but do the
+        // killing after it has been released.
+        let processes: Vec<(String, Arc<RwLock<ProcessControl>>)> = {
+            let mut table = self.processes.write().unwrap();
+            let names: Vec<String> = table
+                .iter()
+                .filter(|(_, ctl)| predicate(&ctl.read().unwrap()))
+                .map(|(name, _)| name.clone())
+                .collect();
+            names
+                .into_iter()
+                .filter_map(|name| table.remove(&name).map(|ctl| (name, ctl)))
+                .collect()
+        };
+        processes
+            .into_iter()
+            .map(|(name, ctl)| {
+                let mut ctl = ctl.write().unwrap();
+                ctl.event_queue.abandon();
+                (name, ctl.kill(self.config.kill_timeout))
+            })
+            .collect()
+    }
+
+    /// Shut the manager down: tell every running director to return, stop
+    /// all processes as `stop_process_graceful` does, with `grace` for all of
+    /// them together, and wait for their monitoring threads to finish. The
+    /// first failure to stop a process is returned, after the others have
+    /// been dealt with. Directors run on the manager afterwards return
+    /// straight away, and closing it again does nothing.
+    pub fn close(&mut self, grace: time::Duration) -> std::result::Result<(), ManagerError> {
+        self.closed.cancel();
+
+        // Keep the processes from being restarted, and their monitoring from
+        // waiting on a director, which may have returned already, to take
+        // their final events or make room for more.
+        let processes = self.snapshot();
+        let monitors: Vec<_> = processes
+            .iter()
+            .filter_map(|(_name, ctl)| {
+                let mut ctl = ctl.write().unwrap();
+                ctl.detached = true;
+                ctl.event_queue.abandon();
+                ctl.monitor.take()
+            })
+            .collect();
+
+        let manager = &*self;
+        let results: Vec<_> = thread::scope(|scope| {
+            let stopping: Vec<_> = processes
+                .iter()
+                .map(|(name, _ctl)| scope.spawn(move || manager.stop_process_graceful(name, grace)))
+                .collect();
+            stopping.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+        for monitor in monitors {
+            let _ = monitor.join();
+        }
+
+        for result in results {
+            match result {
+                // Retired meanwhile by a director that had yet to return.
+                Ok(_) | Err(ManagerError::ProcessUnknown) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Kill every managed process, returning the outcome for each by name.
+    pub fn stop_all(&mut self) -> Vec<(String, std::result::Result<(), ManagerError>)> {
+        self.stop_where(|_| true)
+    }
+
+    /// Kill every managed process labelled with `tag`, returning the outcome
+    /// for each by name.
+    pub fn stop_by_tag(
+        &mut self,
+        tag: &str,
+    ) -> Vec<(String, std::result::Result<(), ManagerError>)> {
+        self.stop_where(|ctl| ctl.tags.contains(tag))
+    }
+
+    /// Kill the managed process `name`, waiting at most the configured kill
+    /// timeout for it to die.
+    pub fn stop_process(&mut self, name: &str) -> std::result::Result<(), ManagerError> {
+        // Take the process out of the table, but do the killing after the
+        // table's lock has been released.
+        let ctl = self.processes.write().unwrap().remove(name);
+        match ctl {
+            Some(ctl) => {
+                let mut ctl = ctl.write().unwrap();
+                ctl.event_queue.abandon();
+                ctl.kill(self.config.kill_timeout)
+            }
+            None => Err(ManagerError::ProcessUnknown),
+        }
+    }
+}
+
+/// A managed process, as returned by `ProcessManager::spawn_handle`. Each
+/// method is the manager's method of the same name applied to the process.
+/// Once the process has been retired they report `ProcessUnknown`, and should
+/// its name then be reused, they apply to the new process instead.
+#[derive(Clone)]
+pub struct ProcessHandle {
+    manager: ProcessManager,
+    name: String,
+}
+
+impl ProcessHandle {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn pid(&self) -> std::result::Result<u32, ManagerError> {
+        self.manager.pid(&self.name)
+    }
+
+    pub fn status(&self) -> std::result::Result<ProcessStatus, ManagerError> {
+        self.manager.status(&self.name)
+    }
+
+    pub fn stop(&mut self) -> std::result::Result<(), ManagerError> {
+        self.manager.stop_process(&self.name)
+    }
+
+    pub fn send_signal(&self, signal: i32) -> std::result::Result<(), ManagerError> {
+        self.manager.send_signal(&self.name, signal)
+    }
+
+    pub fn send_input(&self, data: &[u8]) -> std::result::Result<(), ManagerError> {
+        self.manager.send_input(&self.name, data)
+    }
+}
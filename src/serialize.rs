@@ -0,0 +1,70 @@
+//! Serde representations for the standard library types carried by events,
+//! which do not implement `Serialize` and `Deserialize` themselves.
+
+/// An `ExitStatus` is represented by its exit code, or by the signal that
+/// terminated the process if there was one.
+pub mod exit_status {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::process::ExitStatus;
+
+    #[derive(Serialize, Deserialize)]
+    struct Repr {
+        code: Option<i32>,
+        signal: Option<i32>,
+    }
+
+    pub fn serialize<S: Serializer>(status: &ExitStatus, serializer: S) -> Result<S::Ok, S::Error> {
+        Repr {
+            code: status.code(),
+            signal: signal(status),
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ExitStatus, D::Error> {
+        Repr::deserialize(deserializer).map(from_repr)
+    }
+
+    #[cfg(unix)]
+    fn signal(status: &ExitStatus) -> Option<i32> {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal()
+    }
+
+    #[cfg(not(unix))]
+    fn signal(_status: &ExitStatus) -> Option<i32> {
+        None
+    }
+
+    /// Rebuild a raw wait status, which keeps a terminating signal in its low
+    /// seven bits and otherwise the exit code in its second byte.
+    #[cfg(unix)]
+    fn from_repr(repr: Repr) -> ExitStatus {
+        use std::os::unix::process::ExitStatusExt;
+        match repr.signal {
+            Some(signal) => ExitStatus::from_raw(signal & 0x7f),
+            None => ExitStatus::from_raw((repr.code.unwrap_or(0) & 0xff) << 8),
+        }
+    }
+
+    #[cfg(windows)]
+    fn from_repr(repr: Repr) -> ExitStatus {
+        use std::os::windows::process::ExitStatusExt;
+        ExitStatus::from_raw(repr.code.unwrap_or(0) as u32)
+    }
+}
+
+/// An `io::Error` is represented by its message alone, and comes back as an
+/// error of kind `Other`.
+pub mod io_error {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::io::Error;
+
+    pub fn serialize<S: Serializer>(error: &Error, serializer: S) -> Result<S::Ok, S::Error> {
+        error.to_string().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Error, D::Error> {
+        String::deserialize(deserializer).map(Error::other)
+    }
+}
@@ -0,0 +1,44 @@
+#![cfg(all(unix, feature = "encoding_rs"))]
+
+use procman::*;
+use std::process::Command;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+fn texts(encoding: &'static encoding_rs::Encoding, bytes: &str) -> String {
+    // Read a byte at a time, so that every multibyte character is split.
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_handles(HandleSet::STDOUT)
+        .with_read_buffer_size(1)
+        .with_text_output(Encoding::Named(encoding));
+    man.spawn("foo".to_string(), Command::new("printf").arg(bytes))
+        .expect("spawn failed");
+    let text: Arc<RwLock<String>> = Default::default();
+    let inner_text = text.clone();
+    man.run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+        match &ev {
+            ProcessEvent::Text(_handle, chunk) => inner_text.write().unwrap().push_str(chunk),
+            ProcessEvent::Output(..) | ProcessEvent::Error(_) => panic!("unexpected {}", ev),
+            _ => (),
+        }
+        k(ev)
+    })
+    .expect("run_director failed");
+    let text = text.read().unwrap().clone();
+    text
+}
+
+#[test]
+fn test_named_encoding() {
+    assert_eq!(texts(encoding_rs::WINDOWS_1252, "caf\\351 \\200"), "café €");
+    assert_eq!(
+        texts(encoding_rs::SHIFT_JIS, "\\223\\372\\226\\173"),
+        "日本"
+    );
+    // What is left of a character at the end is replaced.
+    assert_eq!(
+        texts(encoding_rs::SHIFT_JIS, "\\223\\372\\226"),
+        "日\u{fffd}"
+    );
+}
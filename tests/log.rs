@@ -0,0 +1,51 @@
+#![cfg(all(unix, feature = "log"))]
+
+use log::{Level, Log, Metadata, Record};
+use procman::*;
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::Duration;
+
+struct CapturingLogger(Mutex<Vec<(Level, String)>>);
+
+impl Log for CapturingLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        self.0
+            .lock()
+            .unwrap()
+            .push((record.level(), record.args().to_string()));
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger(Mutex::new(Vec::new()));
+
+#[test]
+fn test_lifecycle_logs() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Info);
+
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn("short".to_string(), &mut Command::new("true"))
+        .expect("spawn failed");
+    let pid = man.pid("short").expect("pid failed");
+    man.run_director().expect("run_director failed");
+
+    let records = LOGGER.0.lock().unwrap();
+    assert_eq!(
+        records[0],
+        (Level::Info, format!("spawned process short (pid {})", pid))
+    );
+    assert_eq!(
+        records[1],
+        (
+            Level::Info,
+            format!("process short (pid {}) exited: exit status: 0", pid)
+        )
+    );
+}
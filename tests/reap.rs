@@ -0,0 +1,67 @@
+//! These tests count this process's zombie children, so they live in their
+//! own test binary where no other tests are running processes.
+
+#![cfg(unix)]
+
+use procman::*;
+use std::io::Result;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+/// Return the pids of this process's children that have exited but have not
+/// been reaped.
+fn zombies() -> Vec<String> {
+    let parent = std::process::id().to_string();
+    std::fs::read_dir("/proc")
+        .unwrap()
+        .filter_map(|entry| {
+            let pid = entry.ok()?.file_name().into_string().ok()?;
+            let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+            // The fields after the parenthesised command name begin with the
+            // state and the parent pid.
+            let fields: Vec<&str> = stat[stat.rfind(')')? + 2..].split(' ').collect();
+            if fields[0] == "Z" && fields[1] == parent {
+                Some(pid)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_no_zombies() {
+    let mut man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+
+    for round in 0..20 {
+        for i in 0..10 {
+            man.spawn(format!("true-{}-{}", round, i), &mut Command::new("true"))
+                .expect("spawn failed");
+        }
+        man.run_director().expect("run_director failed");
+    }
+    assert_eq!(zombies(), Vec::<String>::new());
+
+    for i in 0..10 {
+        man.spawn(format!("sleep-{}", i), Command::new("sleep").arg("100"))
+            .expect("spawn failed");
+    }
+    for (name, result) in man.stop_all() {
+        result.unwrap_or_else(|e| panic!("stopping {} failed: {}", name, e));
+    }
+    assert_eq!(zombies(), Vec::<String>::new());
+
+    let inner = man.clone();
+    thread::spawn(move || {
+        inner.run_process_with_policy(
+            "restarted".to_string(),
+            &mut Command::new("true"),
+            RestartPolicy::Always { max: 50 },
+            |ev, k: &dyn Fn(ProcessEvent) -> Result<()>| k(ev),
+        )
+    });
+    thread::sleep(Duration::from_millis(50));
+    man.run_director().expect("run_director failed");
+    assert_eq!(zombies(), Vec::<String>::new());
+}
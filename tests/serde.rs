@@ -0,0 +1,28 @@
+#![cfg(all(unix, feature = "serde"))]
+
+use procman::*;
+use std::process::Command;
+
+#[test]
+fn test_serde_round_trip() {
+    let ev = ProcessEvent::Output(HandleType::StdError, vec![0xff, b'h', b'i']);
+    let json = serde_json::to_string(&ev).expect("serialize failed");
+    match serde_json::from_str(&json).expect("deserialize failed") {
+        ProcessEvent::Output(HandleType::StdError, bytes) => {
+            assert_eq!(bytes, vec![0xff, b'h', b'i'])
+        }
+        other => panic!("expected Output, got {:?}", other),
+    }
+
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg("exit 3")
+        .status()
+        .expect("sh failed");
+    let json = serde_json::to_string(&ProcessEvent::Exited(status)).expect("serialize failed");
+    assert_eq!(json, r#"{"Exited":{"code":3,"signal":null}}"#);
+    match serde_json::from_str(&json).expect("deserialize failed") {
+        ProcessEvent::Exited(status) => assert_eq!(status.code(), Some(3)),
+        other => panic!("expected Exited, got {:?}", other),
+    }
+}
@@ -1,9 +1,13 @@
+#![cfg(unix)]
+
 use procman::*;
+use std::collections::{HashMap, HashSet};
 use std::io::Result;
 use std::process::Command;
 use std::sync::Arc;
 use std::sync::RwLock;
 use std::thread;
+use std::time::Duration;
 
 #[test]
 fn test_run() {
@@ -18,14 +22,8 @@ fn test_run() {
             Command::new("echo").arg("hello"),
             move |ev: ProcessEvent, k: &dyn Fn(ProcessEvent) -> Result<()>| {
                 println!("event: {}", ev);
-                if let ProcessEvent::Output(_handle, bytes, len) = &ev {
-                    if *len > 0 {
-                        *inner_flag.write().unwrap() = Some({
-                            let mut b = bytes.clone();
-                            b.truncate(*len);
-                            b
-                        })
-                    }
+                if let ProcessEvent::Output(_handle, bytes) = &ev {
+                    *inner_flag.write().unwrap() = Some(bytes.clone())
                 };
                 k(ev)
             },
@@ -37,5 +35,2869 @@ fn test_run() {
 
     let mv = flag.read().unwrap();
     let v = mv.as_ref().unwrap();
-    assert_eq!(&v[..v.len()], "hello\n".as_bytes());
+    assert_eq!(&v[..], "hello\n".as_bytes());
+}
+
+#[test]
+fn test_eof() {
+    let man = ProcessManager::new();
+    let inner = man.clone();
+    let events: Arc<RwLock<Vec<String>>> = Default::default();
+    let inner_events = events.clone();
+
+    thread::spawn(move || {
+        inner.run_process_with_intercept(
+            "foo".to_string(),
+            Command::new("echo").arg("hello"),
+            move |ev: ProcessEvent, k: &dyn Fn(ProcessEvent) -> Result<()>| {
+                inner_events.write().unwrap().push(format!("{}", ev));
+                k(ev)
+            },
+        )
+    });
+
+    man.run_director().expect("run_director failed");
+
+    let events = events.read().unwrap();
+    let outputs: Vec<&String> = events.iter().filter(|e| e.starts_with("Output")).collect();
+    assert_eq!(outputs, vec!["Output(StdOutput, \"hello\\n\", 6)"]);
+    let eof = events.iter().position(|e| e == "Eof(StdOutput)").unwrap();
+    let output = events.iter().position(|e| e.starts_with("Output")).unwrap();
+    assert!(output < eof);
+    assert_eq!(events.iter().filter(|e| e.starts_with("Eof")).count(), 2);
+    assert!(events.last().unwrap().starts_with("Exited"));
+}
+
+#[test]
+fn test_silent_process_can_be_stopped() {
+    let mut man = ProcessManager::new();
+    let inner = man.clone();
+
+    let monitor = thread::spawn(move || {
+        inner.run_process("quiet".to_string(), Command::new("sleep").arg("100"))
+    });
+
+    // The child never writes anything, so a blocking read would hold the
+    // process lock forever and `stop_process` could never acquire it.
+    thread::sleep(Duration::from_millis(500));
+    man.stop_process("quiet").expect("stop_process failed");
+    monitor.join().unwrap().expect("run_process failed");
+}
+
+#[test]
+fn test_silent_process_reports_no_errors() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn("quiet".to_string(), Command::new("sleep").arg("0.3"))
+        .expect("spawn failed");
+
+    let events: Arc<RwLock<Vec<String>>> = Default::default();
+    let inner_events = events.clone();
+    man.run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+        inner_events.write().unwrap().push(format!("{}", ev));
+        k(ev)
+    })
+    .expect("run_director failed");
+
+    let events = events.read().unwrap();
+    assert!(!events.iter().any(|ev| ev.starts_with("Error")));
+    assert_eq!(events.len(), 3);
+}
+
+#[test]
+fn test_kill_on_drop() {
+    let alive = |pid: u32| unsafe { libc::kill(pid as libc::pid_t, 0) } == 0;
+
+    let pid = {
+        let man = ProcessManager::new()
+            .with_poll_interval(Duration::from_millis(1))
+            .with_kill_on_drop(true);
+        let inner = man.clone();
+        man.spawn("foo".to_string(), Command::new("sleep").arg("100"))
+            .expect("spawn failed");
+        let pid = man.pid("foo").expect("pid failed");
+        drop(man);
+        // A remaining clone keeps the process running.
+        thread::sleep(Duration::from_millis(50));
+        assert!(alive(pid));
+        drop(inner);
+        pid
+    };
+    // The child is reaped as it is killed, so its pid is gone at once.
+    assert!(!alive(pid));
+
+    let mut man = ProcessManager::new();
+    man.spawn("foo".to_string(), Command::new("sleep").arg("100"))
+        .expect("spawn failed");
+    let pid = man.pid("foo").expect("pid failed");
+    let inner = man.clone();
+    drop(inner);
+    assert!(alive(pid));
+    man.stop_process("foo").expect("stop_process failed");
+}
+
+#[test]
+fn test_pid() {
+    let mut man = ProcessManager::new();
+    let inner = man.clone();
+
+    thread::spawn(move || inner.run_process("foo".to_string(), Command::new("sleep").arg("1")));
+    thread::sleep(Duration::from_millis(300));
+
+    assert_ne!(man.pid("foo").expect("pid failed"), 0);
+    assert!(matches!(man.pid("bar"), Err(ManagerError::ProcessUnknown)));
+    man.stop_process("foo").expect("stop_process failed");
+}
+
+#[test]
+fn test_uptime() {
+    let mut man = ProcessManager::new();
+    man.spawn("foo".to_string(), Command::new("sleep").arg("2"))
+        .expect("spawn failed");
+    thread::sleep(Duration::from_millis(100));
+
+    let uptime = man.uptime("foo").expect("uptime failed");
+    assert!(uptime >= Duration::from_millis(100));
+    assert!(uptime < Duration::from_millis(1000));
+    assert!(matches!(
+        man.uptime("bar"),
+        Err(ManagerError::ProcessUnknown)
+    ));
+    man.stop_process("foo").expect("stop_process failed");
+}
+
+#[test]
+fn test_name_conflict() {
+    let mut man = ProcessManager::new();
+    let inner = man.clone();
+
+    let first = thread::spawn(move || {
+        inner.run_process("foo".to_string(), Command::new("sleep").arg("1"))
+    });
+    thread::sleep(Duration::from_millis(300));
+    let pid = man.pid("foo").expect("pid failed");
+
+    match man.run_process("foo".to_string(), &mut Command::new("echo")) {
+        Err(ManagerError::NameConflict(name)) => assert_eq!(name, "foo"),
+        other => panic!("expected NameConflict, got {:?}", other),
+    }
+    assert_eq!(man.pid("foo").expect("pid failed"), pid);
+
+    man.stop_process("foo").expect("stop_process failed");
+    assert!(first.join().unwrap().is_ok());
+}
+
+#[test]
+fn test_spawn_failed() {
+    let man = ProcessManager::new();
+    match man.spawn(
+        "foo".to_string(),
+        &mut Command::new("procman-no-such-program"),
+    ) {
+        Err(ManagerError::SpawnFailed { name, source }) => {
+            assert_eq!(name, "foo");
+            assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+        }
+        other => panic!("expected SpawnFailed, got {:?}", other),
+    }
+    assert!(man.is_empty());
+}
+
+#[test]
+fn test_manager_error_display() {
+    assert_eq!(format!("{}", ManagerError::ProcessUnknown), "process unknown");
+    assert_eq!(
+        format!("{}", ManagerError::NameConflict("foo".to_string())),
+        "name conflict: foo"
+    );
+
+    let boxed: Box<dyn std::error::Error> = Box::new(ManagerError::ProcessUnknown);
+    assert_eq!(boxed.to_string(), "process unknown");
+}
+
+#[test]
+fn test_output_display() {
+    let ev = ProcessEvent::Output(HandleType::StdOutput, b"caf\xc3".to_vec());
+    assert_eq!(format!("{}", ev), "Output(StdOutput, \"caf\u{fffd}\", 4)");
+}
+
+#[test]
+fn test_handle_type_display() {
+    assert_eq!(HandleType::StdInput.to_string(), "stdin");
+    assert_eq!(HandleType::StdOutput.to_string(), "stdout");
+    assert_eq!(HandleType::StdError.to_string(), "stderr");
+}
+
+#[test]
+fn test_send_input() {
+    let mut man = ProcessManager::new();
+    let inner = man.clone();
+    let flag: Arc<RwLock<Vec<u8>>> = Default::default();
+    let inner_flag = flag.clone();
+
+    thread::spawn(move || {
+        inner.run_process_with_intercept(
+            "cat".to_string(),
+            &mut Command::new("cat"),
+            move |ev: ProcessEvent, k: &dyn Fn(ProcessEvent) -> Result<()>| {
+                if let ProcessEvent::Output(HandleType::StdOutput, bytes) = &ev {
+                    inner_flag.write().unwrap().extend_from_slice(bytes);
+                }
+                k(ev)
+            },
+        )
+    });
+    thread::sleep(Duration::from_millis(300));
+
+    man.send_input("cat", b"hi\n").expect("send_input failed");
+    for _ in 0..50 {
+        if !flag.read().unwrap().is_empty() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    assert_eq!(&flag.read().unwrap()[..], b"hi\n");
+
+    assert!(matches!(
+        man.send_input("dog", b"hi\n"),
+        Err(ManagerError::ProcessUnknown)
+    ));
+    man.stop_process("cat").expect("stop_process failed");
+}
+
+#[test]
+fn test_send_line() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn(
+        "count".to_string(),
+        Command::new("sh")
+            .arg("-c")
+            .arg("n=0; while read line; do n=$((n + 1)); done; echo $n"),
+    )
+    .expect("spawn failed");
+
+    for line in &["one", "two", "three"] {
+        man.send_line("count", line).expect("send_line failed");
+    }
+    man.close_stdin("count").expect("close_stdin failed");
+    man.close_stdin("count").expect("close_stdin failed");
+    assert!(matches!(
+        man.send_line("count", "four"),
+        Err(ManagerError::StdinClosed)
+    ));
+    assert!(matches!(
+        man.close_stdin("dog"),
+        Err(ManagerError::ProcessUnknown)
+    ));
+
+    let output: Arc<RwLock<Vec<u8>>> = Default::default();
+    let inner_output = output.clone();
+    man.run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+        if let ProcessEvent::Output(_handle, bytes) = &ev {
+            inner_output.write().unwrap().extend(bytes);
+        }
+        k(ev)
+    })
+    .expect("run_director failed");
+    assert_eq!(*output.read().unwrap(), b"3\n");
+}
+
+#[test]
+fn test_send_large_input() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn("cat".to_string(), &mut Command::new("cat"))
+        .expect("spawn failed");
+
+    // More than a pipe holds, so that some of it has to wait for `cat`.
+    let input: Vec<u8> = (0..200_000).map(|i| b'a' + (i % 26) as u8).collect();
+    man.send_input("cat", &input).expect("send_input failed");
+    man.close_stdin("cat").expect("close_stdin failed");
+
+    let output: Arc<RwLock<Vec<u8>>> = Default::default();
+    let inner_output = output.clone();
+    man.run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+        if let ProcessEvent::Output(_handle, bytes) = &ev {
+            inner_output.write().unwrap().extend(bytes);
+        }
+        k(ev)
+    })
+    .expect("run_director failed");
+    assert!(*output.read().unwrap() == input);
+}
+
+#[test]
+fn test_error_names_process() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn(
+        "closer".to_string(),
+        Command::new("sh").args(["-c", "sleep 0.2; exec 0<&-; sleep 0.3"]),
+    )
+    .expect("spawn failed");
+    man.spawn("other".to_string(), Command::new("sleep").arg("0.5"))
+        .expect("spawn failed");
+
+    // More than a pipe holds, so that the rest is still waiting when the
+    // reader goes away.
+    man.send_input("closer", &[b'x'; 200_000])
+        .expect("send_input failed");
+
+    let errors: Arc<RwLock<Vec<String>>> = Default::default();
+    let inner_errors = errors.clone();
+    man.run_director_with_named_intercept(move |name, ev, k: &mut dyn FnMut(ProcessEvent)| {
+        if let ProcessEvent::Error(ProcessError::ErrorWriting(_)) = &ev {
+            inner_errors.write().unwrap().push(name.to_string());
+        }
+        k(ev)
+    })
+    .expect("run_director failed");
+    assert_eq!(*errors.read().unwrap(), vec!["closer".to_string()]);
+}
+
+#[test]
+fn test_stdin_from_file() {
+    let path = std::env::temp_dir().join(format!("procman-stdin-{}", std::process::id()));
+    std::fs::write(&path, "one\ntwo\nthree\n").unwrap();
+
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_stdin_from_file(path.clone());
+    man.spawn("wc".to_string(), Command::new("wc").arg("-l"))
+        .expect("spawn failed");
+    assert!(matches!(
+        man.send_input("wc", b"four\n"),
+        Err(ManagerError::InvalidConfig(_))
+    ));
+
+    let output: Arc<RwLock<Vec<u8>>> = Default::default();
+    let inner_output = output.clone();
+    man.run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+        if let ProcessEvent::Output(_handle, bytes) = &ev {
+            inner_output.write().unwrap().extend(bytes);
+        }
+        k(ev)
+    })
+    .expect("run_director failed");
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.read().unwrap()).trim(), "3");
+}
+
+#[test]
+fn test_poll_interval() {
+    assert_eq!(
+        ProcessManager::new().poll_interval(),
+        Duration::from_millis(200)
+    );
+
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    assert_eq!(man.poll_interval(), Duration::from_millis(1));
+
+    let inner = man.clone();
+    let start = std::time::Instant::now();
+    thread::spawn(move || inner.run_process("foo".to_string(), &mut Command::new("true")));
+    thread::sleep(Duration::from_millis(50));
+    man.run_director().expect("run_director failed");
+    assert!(start.elapsed() < Duration::from_millis(1000));
+}
+
+#[test]
+fn test_with_config() {
+    let man = ProcessManager::with_config(ProcessManagerConfig {
+        poll_interval: Duration::from_millis(1),
+        line_buffering: true,
+        ..Default::default()
+    });
+    assert_eq!(man.config().poll_interval, Duration::from_millis(1));
+    assert!(man.config().line_buffering);
+    assert_eq!(man.read_buffer_size(), 8192);
+
+    let inner = man.clone();
+    assert_eq!(inner.poll_interval(), Duration::from_millis(1));
+    let start = std::time::Instant::now();
+    thread::spawn(move || inner.run_process("foo".to_string(), &mut Command::new("true")));
+    thread::sleep(Duration::from_millis(50));
+    man.run_director().expect("run_director failed");
+    assert!(start.elapsed() < Duration::from_millis(1000));
+}
+
+#[test]
+fn test_default_env_and_cwd() {
+    let mut env = HashMap::new();
+    env.insert("PROCMAN_DEFAULT".to_string(), "default".to_string());
+    env.insert("PROCMAN_EXPLICIT".to_string(), "default".to_string());
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_default_env(env)
+        .with_default_cwd(std::env::temp_dir());
+    let events = man.subscribe();
+
+    man.spawn(
+        "env".to_string(),
+        Command::new("printenv")
+            .arg("PROCMAN_DEFAULT")
+            .arg("PROCMAN_EXPLICIT")
+            .env("PROCMAN_EXPLICIT", "explicit"),
+    )
+    .expect("spawn failed");
+    man.spawn("cwd".to_string(), &mut Command::new("pwd"))
+        .expect("spawn failed");
+    man.spawn(
+        "explicit-cwd".to_string(),
+        Command::new("pwd").current_dir("/"),
+    )
+    .expect("spawn failed");
+    man.run_director().expect("run_director failed");
+
+    let mut outputs: HashMap<String, Vec<u8>> = HashMap::new();
+    for (name, ev) in events.try_iter() {
+        if let ProcessEvent::Output(_handle, bytes) = ev {
+            outputs.entry(name).or_default().extend_from_slice(&bytes);
+        }
+    }
+    assert_eq!(outputs["env"], b"default\nexplicit\n");
+    let temp_dir = std::env::temp_dir().canonicalize().unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&outputs["cwd"]).trim_end(),
+        temp_dir.to_string_lossy()
+    );
+    assert_eq!(outputs["explicit-cwd"], b"/\n");
+}
+
+#[test]
+fn test_env_clear() {
+    let mut env = HashMap::new();
+    env.insert("PROCMAN_DEFAULT".to_string(), "default".to_string());
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_default_env(env)
+        .with_env_clear(true);
+    let events = man.subscribe();
+
+    man.spawn("default".to_string(), &mut Command::new("printenv"))
+        .expect("spawn failed");
+    man.spawn(
+        "explicit".to_string(),
+        Command::new("printenv")
+            .env("PROCMAN_EXPLICIT", "explicit")
+            .env_remove("PROCMAN_DEFAULT"),
+    )
+    .expect("spawn failed");
+    man.run_director().expect("run_director failed");
+
+    let mut outputs: HashMap<String, Vec<u8>> = HashMap::new();
+    for (name, ev) in events.try_iter() {
+        if let ProcessEvent::Output(_handle, bytes) = ev {
+            outputs.entry(name).or_default().extend_from_slice(&bytes);
+        }
+    }
+    assert_eq!(outputs["default"], b"PROCMAN_DEFAULT=default\n");
+    assert_eq!(outputs["explicit"], b"PROCMAN_EXPLICIT=explicit\n");
+}
+
+#[test]
+fn test_read_buffer_size() {
+    assert_eq!(ProcessManager::new().read_buffer_size(), 8192);
+
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_read_buffer_size(4);
+    let inner = man.clone();
+    let chunks: Arc<RwLock<Vec<Vec<u8>>>> = Default::default();
+    let inner_chunks = chunks.clone();
+
+    thread::spawn(move || {
+        inner.run_process_with_intercept(
+            "foo".to_string(),
+            Command::new("echo").arg("hello world"),
+            move |ev: ProcessEvent, k: &dyn Fn(ProcessEvent) -> Result<()>| {
+                if let ProcessEvent::Output(_handle, bytes) = &ev {
+                    inner_chunks.write().unwrap().push(bytes.clone());
+                }
+                k(ev)
+            },
+        )
+    });
+    thread::sleep(Duration::from_millis(50));
+    man.run_director().expect("run_director failed");
+
+    let chunks = chunks.read().unwrap();
+    assert!(chunks.len() >= 3);
+    assert!(chunks.iter().all(|c| c.len() <= 4));
+    assert_eq!(chunks.concat(), b"hello world\n");
+}
+
+#[test]
+fn test_coalesce() {
+    let chunks = |coalesce: bool| {
+        let man = ProcessManager::new()
+            .with_poll_interval(Duration::from_millis(10))
+            .with_read_buffer_size(16)
+            .with_coalesce(coalesce);
+        man.spawn(
+            "foo".to_string(),
+            Command::new("sh")
+                .arg("-c")
+                .arg("for i in $(seq 1 100); do echo line $i; done; sleep 0.1"),
+        )
+        .expect("spawn failed");
+
+        let chunks: Arc<RwLock<Vec<Vec<u8>>>> = Default::default();
+        let inner_chunks = chunks.clone();
+        man.run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+            if let ProcessEvent::Output(_handle, bytes) = &ev {
+                inner_chunks.write().unwrap().push(bytes.clone());
+            }
+            k(ev)
+        })
+        .expect("run_director failed");
+        let chunks = chunks.read().unwrap().clone();
+        chunks
+    };
+
+    let expected: String = (1..=100).map(|i| format!("line {}\n", i)).collect();
+    let separate = chunks(false);
+    let coalesced = chunks(true);
+    assert_eq!(separate.concat(), expected.as_bytes());
+    assert_eq!(coalesced.concat(), expected.as_bytes());
+    assert!(separate.iter().all(|c| c.len() <= 16));
+    assert!(coalesced.len() < separate.len());
+    assert!(coalesced.iter().any(|c| c.len() > 16));
+}
+
+#[test]
+fn test_output_rate_limit() {
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_output_rate_limit(100_000);
+    // More than a pipe holds, so that the producer has to wait for us.
+    man.spawn(
+        "flood".to_string(),
+        Command::new("head").args(["-c", "300000", "/dev/zero"]),
+    )
+    .expect("spawn failed");
+
+    let received: Arc<RwLock<usize>> = Default::default();
+    let throttled: Arc<RwLock<usize>> = Default::default();
+    let (inner_received, inner_throttled) = (received.clone(), throttled.clone());
+    let start = std::time::Instant::now();
+    man.run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+        match &ev {
+            ProcessEvent::Output(_handle, bytes) => *inner_received.write().unwrap() += bytes.len(),
+            ProcessEvent::Throttled => *inner_throttled.write().unwrap() += 1,
+            _ => {}
+        }
+        k(ev)
+    })
+    .expect("run_director failed");
+    assert_eq!(*received.read().unwrap(), 300_000);
+    assert!(*throttled.read().unwrap() >= 1);
+    // A second's worth at once, and then at the limit until what is left
+    // fits in the pipe.
+    assert!(start.elapsed() >= Duration::from_secs(1));
+
+    let man = ProcessManager::new().with_output_rate_limit(0);
+    assert!(matches!(
+        man.spawn("none".to_string(), &mut Command::new("true")),
+        Err(ManagerError::InvalidConfig(_))
+    ));
+}
+
+#[test]
+fn test_name_prefix() {
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_line_buffering(true)
+        .with_name_prefix(true);
+    man.spawn("foo".to_string(), Command::new("echo").arg("hello"))
+        .expect("spawn failed");
+    man.spawn(
+        "bar".to_string(),
+        Command::new("printf").arg("one\\ntwo\\n"),
+    )
+    .expect("spawn failed");
+
+    let lines: Arc<RwLock<Vec<String>>> = Default::default();
+    let inner_lines = lines.clone();
+    man.run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+        if let ProcessEvent::Line(_handle, line) = &ev {
+            inner_lines.write().unwrap().push(line.clone());
+        }
+        k(ev)
+    })
+    .expect("run_director failed");
+    let mut lines = lines.read().unwrap().clone();
+    lines.sort();
+    assert_eq!(lines, vec!["[bar] one", "[bar] two", "[foo] hello"]);
+
+    // Text arrives in chunks that need not line up with lines.
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_handles(HandleSet::STDOUT)
+        .with_read_buffer_size(4)
+        .with_text_output(Encoding::Utf8Lossy)
+        .with_name_prefix(true);
+    man.spawn(
+        "foo".to_string(),
+        Command::new("printf").arg("hello\\nworld\\n"),
+    )
+    .expect("spawn failed");
+
+    let text: Arc<RwLock<String>> = Default::default();
+    let inner_text = text.clone();
+    man.run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+        if let ProcessEvent::Text(_handle, text) = &ev {
+            inner_text.write().unwrap().push_str(text);
+        }
+        k(ev)
+    })
+    .expect("run_director failed");
+    assert_eq!(*text.read().unwrap(), "[foo] hello\n[foo] world\n");
+}
+
+#[test]
+fn test_text_output() {
+    let texts = |encoding: Encoding, bytes: &str| {
+        let man = ProcessManager::new()
+            .with_poll_interval(Duration::from_millis(1))
+            .with_handles(HandleSet::STDOUT)
+            .with_text_output(encoding);
+        man.spawn("foo".to_string(), Command::new("printf").arg(bytes))
+            .expect("spawn failed");
+        let events: Arc<RwLock<Vec<String>>> = Default::default();
+        let inner_events = events.clone();
+        man.run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+            match &ev {
+                ProcessEvent::Text(..) | ProcessEvent::Error(_) => {
+                    inner_events.write().unwrap().push(format!("{}", ev))
+                }
+                ProcessEvent::Output(..) => panic!("unexpected {}", ev),
+                _ => (),
+            }
+            k(ev)
+        })
+        .expect("run_director failed");
+        let events = events.read().unwrap().clone();
+        events
+    };
+
+    // "café" in Latin-1, where the é is a single byte that is not UTF-8. In
+    // UTF-8 it begins a character that never ends, and is decoded lossily
+    // once the output ends.
+    assert_eq!(
+        texts(Encoding::Latin1, "caf\\351"),
+        vec!["Text(StdOutput, \"café\")"]
+    );
+    assert_eq!(
+        texts(Encoding::Utf8Lossy, "caf\\351"),
+        vec!["Text(StdOutput, \"caf\")", "Text(StdOutput, \"\u{fffd}\")"]
+    );
+    assert_eq!(
+        texts(Encoding::Utf8Strict, "caf\\303\\251"),
+        vec!["Text(StdOutput, \"café\")"]
+    );
+    assert_eq!(
+        texts(Encoding::Utf8Strict, "caf\\351"),
+        vec!["Text(StdOutput, \"caf\")", "Text(StdOutput, \"\u{fffd}\")"]
+    );
+    // Followed by more output, it is no character at all.
+    let strict = texts(Encoding::Utf8Strict, "caf\\351!");
+    assert_eq!(strict.len(), 1);
+    assert!(strict[0].starts_with("Error(ErrorDecoding: "));
+}
+
+#[test]
+fn test_text_output_split_characters() {
+    let texts = |bytes: &str| {
+        // Read a byte at a time, so that every multibyte character is split.
+        let man = ProcessManager::new()
+            .with_poll_interval(Duration::from_millis(1))
+            .with_handles(HandleSet::STDOUT)
+            .with_read_buffer_size(1)
+            .with_text_output(Encoding::Utf8Strict);
+        man.spawn("foo".to_string(), Command::new("printf").arg(bytes))
+            .expect("spawn failed");
+        let text: Arc<RwLock<String>> = Default::default();
+        let inner_text = text.clone();
+        man.run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+            match &ev {
+                ProcessEvent::Text(_handle, chunk) => inner_text.write().unwrap().push_str(chunk),
+                ProcessEvent::Error(_) => panic!("unexpected {}", ev),
+                _ => (),
+            }
+            k(ev)
+        })
+        .expect("run_director failed");
+        let text = text.read().unwrap().clone();
+        text
+    };
+
+    assert_eq!(texts("caf\\303\\251 \\342\\202\\254"), "café €");
+    // What is left of a character at the end is decoded lossily.
+    assert_eq!(texts("ab\\342\\202"), "ab\u{fffd}");
+}
+
+#[test]
+fn test_strip_ansi() {
+    // The small buffer cuts the escape sequences across reads.
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_read_buffer_size(4)
+        .with_strip_ansi(true)
+        .with_output_history(10);
+    man.spawn(
+        "foo".to_string(),
+        Command::new("printf").arg("\\033[1;31mred\\033[0m \\033(Bplain\\n"),
+    )
+    .expect("spawn failed");
+    man.wait_for_process("foo")
+        .expect("wait_for_process failed");
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(man.recent_output("foo").unwrap(), vec!["red \u{1b}(Bplain"]);
+
+    let output: Arc<RwLock<Vec<u8>>> = Default::default();
+    let inner_output = output.clone();
+    man.run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+        if let ProcessEvent::Output(_handle, bytes) = &ev {
+            inner_output.write().unwrap().extend(bytes);
+        }
+        k(ev)
+    })
+    .expect("run_director failed");
+    assert_eq!(*output.read().unwrap(), b"red \x1b(Bplain\n");
+}
+
+#[test]
+fn test_line_buffering() {
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_line_buffering(true);
+    let inner = man.clone();
+    let events: Arc<RwLock<Vec<String>>> = Default::default();
+    let inner_events = events.clone();
+
+    thread::spawn(move || {
+        inner.run_process_with_intercept(
+            "foo".to_string(),
+            Command::new("printf").arg("a\\nb\\n"),
+            move |ev: ProcessEvent, k: &dyn Fn(ProcessEvent) -> Result<()>| {
+                match &ev {
+                    ProcessEvent::Line(_handle, line) => {
+                        inner_events.write().unwrap().push(line.clone())
+                    }
+                    ProcessEvent::Output(_handle, _bytes) => {
+                        inner_events.write().unwrap().push("<raw output>".to_string())
+                    }
+                    _ => (),
+                }
+                k(ev)
+            },
+        )
+    });
+    thread::sleep(Duration::from_millis(50));
+    man.run_director().expect("run_director failed");
+
+    assert_eq!(*events.read().unwrap(), vec!["a", "b"]);
+}
+
+#[test]
+fn test_spawn() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    for name in &["a", "b", "c"] {
+        man.spawn(name.to_string(), Command::new("echo").arg(name))
+            .expect("spawn failed");
+    }
+
+    let exits: Arc<RwLock<usize>> = Default::default();
+    let inner_exits = exits.clone();
+    man.run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+        if let ProcessEvent::Exited(_status) = &ev {
+            *inner_exits.write().unwrap() += 1;
+        }
+        k(ev)
+    })
+    .expect("run_director failed");
+
+    assert_eq!(*exits.read().unwrap(), 3);
+}
+
+#[test]
+fn test_spawn_handle() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    let mut handle = man
+        .spawn_handle("foo".to_string(), Command::new("sleep").arg("100"))
+        .expect("spawn_handle failed");
+    assert_eq!(handle.name(), "foo");
+    assert_eq!(handle.pid().unwrap(), man.pid("foo").unwrap());
+    assert_eq!(handle.status().unwrap(), ProcessStatus::Running);
+
+    handle.stop().expect("stop failed");
+    assert!(man.is_empty());
+    assert!(matches!(handle.pid(), Err(ManagerError::ProcessUnknown)));
+    assert!(matches!(
+        handle.send_signal(libc::SIGTERM),
+        Err(ManagerError::ProcessUnknown)
+    ));
+}
+
+#[test]
+fn test_spawn_replicas() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    let names = man
+        .spawn_replicas("worker", 3, |index| {
+            let mut command = Command::new("echo");
+            command.arg(index.to_string());
+            command
+        })
+        .expect("spawn_replicas failed");
+    assert_eq!(names, vec!["worker-0", "worker-1", "worker-2"]);
+
+    let mut listed = man.list();
+    listed.sort();
+    assert_eq!(listed, names);
+
+    let outputs: Arc<RwLock<HashMap<String, Vec<u8>>>> = Default::default();
+    let inner_outputs = outputs.clone();
+    man.run_director_with_named_intercept(move |name, ev, k: &mut dyn FnMut(ProcessEvent)| {
+        if let ProcessEvent::Output(_handle, bytes) = &ev {
+            inner_outputs
+                .write()
+                .unwrap()
+                .entry(name.to_string())
+                .or_default()
+                .extend(bytes);
+        }
+        k(ev)
+    })
+    .expect("run_director failed");
+
+    let outputs = outputs.read().unwrap();
+    assert_eq!(outputs["worker-0"], b"0\n");
+    assert_eq!(outputs["worker-1"], b"1\n");
+    assert_eq!(outputs["worker-2"], b"2\n");
+}
+
+#[test]
+fn test_start_ordered() {
+    let path = std::env::temp_dir().join(format!("procman-ordered-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    let mut app = Command::new("cat");
+    app.arg(&path);
+    let mut db = Command::new("sh");
+    db.arg("-c")
+        .arg("sleep 0.1; echo db > \"$1\"; echo ready; sleep 0.1")
+        .arg("sh")
+        .arg(&path);
+    man.start_ordered(vec![
+        ProcessSpec::new("app", app).with_dependency("db"),
+        ProcessSpec::new("db", db).with_ready(regex::Regex::new("^ready$").unwrap()),
+    ])
+    .expect("start_ordered failed");
+
+    let outputs: Arc<RwLock<HashMap<String, Vec<u8>>>> = Default::default();
+    let inner_outputs = outputs.clone();
+    let statuses = man
+        .run_director_with_named_intercept(move |name, ev, k: &mut dyn FnMut(ProcessEvent)| {
+            if let ProcessEvent::Output(_handle, bytes) = &ev {
+                inner_outputs
+                    .write()
+                    .unwrap()
+                    .entry(name.to_string())
+                    .or_default()
+                    .extend(bytes);
+            }
+            k(ev)
+        })
+        .expect("run_director failed");
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(statuses["app"].success());
+    assert!(statuses["db"].success());
+    assert_eq!(outputs.read().unwrap()["app"], b"db\n");
+
+    let result = man.start_ordered(vec![
+        ProcessSpec::new("a", Command::new("true")).with_dependency("b"),
+        ProcessSpec::new("b", Command::new("true")).with_dependency("a"),
+        ProcessSpec::new("c", Command::new("true")),
+    ]);
+    match result {
+        Err(ManagerError::InvalidConfig(reason)) => {
+            assert_eq!(reason, "dependency cycle among a, b")
+        }
+        other => panic!("expected InvalidConfig, got {:?}", other),
+    }
+    assert!(man.is_empty());
+
+    let result = man.start_ordered(vec![
+        ProcessSpec::new("a", Command::new("true")),
+        ProcessSpec::new("a", Command::new("true")),
+    ]);
+    match result {
+        Err(ManagerError::InvalidConfig(reason)) => {
+            assert_eq!(reason, "a is given more than once")
+        }
+        other => panic!("expected InvalidConfig, got {:?}", other),
+    }
+    assert!(man.is_empty());
+}
+
+#[test]
+fn test_adopt() {
+    use std::process::Stdio;
+
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    let child = Command::new("echo")
+        .arg("hello")
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("spawn failed");
+    man.adopt("foo".to_string(), child).expect("adopt failed");
+
+    let child = Command::new("true").spawn().expect("spawn failed");
+    match man.adopt("foo".to_string(), child) {
+        Err(ManagerError::NameConflict(name)) => assert_eq!(name, "foo"),
+        other => panic!("expected NameConflict, got {:?}", other),
+    }
+    let child = Command::new("true").spawn().expect("spawn failed");
+    man.adopt("bar".to_string(), child).expect("adopt failed");
+
+    let events: Arc<RwLock<Vec<String>>> = Default::default();
+    let inner_events = events.clone();
+    let statuses = man
+        .run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+            inner_events.write().unwrap().push(format!("{}", ev));
+            k(ev)
+        })
+        .expect("run_director failed");
+    assert!(statuses["foo"].success());
+    assert!(statuses["bar"].success());
+
+    let events = events.read().unwrap();
+    assert!(events.contains(&"Output(StdOutput, \"hello\\n\", 6)".to_string()));
+    assert_eq!(
+        events.iter().filter(|ev| ev.starts_with("Output")).count(),
+        1
+    );
+    assert_eq!(events.iter().filter(|ev| ev.starts_with("Eof")).count(), 1);
+}
+
+#[test]
+fn test_send_signal() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn(
+        "foo".to_string(),
+        Command::new("sh")
+            .arg("-c")
+            .arg("trap 'exit 7' USR1; while true; do sleep 0.01; done"),
+    )
+    .expect("spawn failed");
+
+    match man.send_signal("bar", libc::SIGUSR1) {
+        Err(ManagerError::ProcessUnknown) => {}
+        other => panic!("expected ProcessUnknown, got {:?}", other),
+    }
+
+    // Give the shell time to install its trap.
+    thread::sleep(Duration::from_millis(200));
+    man.send_signal("foo", libc::SIGUSR1)
+        .expect("send_signal failed");
+
+    let statuses = man.run_director().expect("run_director failed");
+    assert_eq!(statuses["foo"].code(), Some(7));
+}
+
+#[test]
+fn test_exit_reason() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn("foo".to_string(), Command::new("sleep").arg("10"))
+        .expect("spawn failed");
+    man.spawn(
+        "bar".to_string(),
+        Command::new("sh").arg("-c").arg("exit 3"),
+    )
+    .expect("spawn failed");
+    man.send_signal("foo", libc::SIGTERM)
+        .expect("send_signal failed");
+
+    let reasons: Arc<RwLock<HashMap<String, ExitReason>>> = Default::default();
+    let inner_reasons = reasons.clone();
+    man.run_director_with_named_intercept(move |name, ev, k: &mut dyn FnMut(ProcessEvent)| {
+        if let Some(reason) = ev.exit_reason() {
+            inner_reasons
+                .write()
+                .unwrap()
+                .insert(name.to_string(), reason);
+        }
+        k(ev)
+    })
+    .expect("run_director failed");
+
+    let reasons = reasons.read().unwrap();
+    assert_eq!(reasons["foo"], ExitReason::Signal(libc::SIGTERM));
+    assert_eq!(reasons["bar"], ExitReason::Code(3));
+}
+
+#[test]
+fn test_signaled() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn("foo".to_string(), Command::new("sleep").arg("10"))
+        .expect("spawn failed");
+    man.spawn("bar".to_string(), &mut Command::new("true"))
+        .expect("spawn failed");
+    man.send_signal("foo", libc::SIGTERM)
+        .expect("send_signal failed");
+
+    let events: Arc<RwLock<Vec<String>>> = Default::default();
+    let inner_events = events.clone();
+    man.run_director_with_named_intercept(move |name, ev, k: &mut dyn FnMut(ProcessEvent)| {
+        if matches!(ev, ProcessEvent::Signaled(_) | ProcessEvent::Exited(_)) {
+            inner_events
+                .write()
+                .unwrap()
+                .push(format!("{} {}", name, ev));
+        }
+        k(ev)
+    })
+    .expect("run_director failed");
+
+    let events = events.read().unwrap();
+    let foo: Vec<&String> = events.iter().filter(|e| e.starts_with("foo")).collect();
+    assert_eq!(foo.len(), 2);
+    assert_eq!(*foo[0], format!("foo Signaled({})", libc::SIGTERM));
+    assert!(foo[1].starts_with("foo Exited"));
+    let bar: Vec<&String> = events.iter().filter(|e| e.starts_with("bar")).collect();
+    assert_eq!(bar.len(), 1);
+    assert!(bar[0].starts_with("bar Exited"));
+}
+
+#[test]
+fn test_exit_code() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn("foo".to_string(), Command::new("sleep").arg("10"))
+        .expect("spawn failed");
+    man.spawn(
+        "bar".to_string(),
+        Command::new("sh").arg("-c").arg("exit 7"),
+    )
+    .expect("spawn failed");
+    man.send_signal("foo", libc::SIGTERM)
+        .expect("send_signal failed");
+
+    let codes: Arc<RwLock<HashMap<String, Option<i32>>>> = Default::default();
+    let inner_codes = codes.clone();
+    man.run_director_with_named_intercept(move |name, ev, k: &mut dyn FnMut(ProcessEvent)| {
+        if let ProcessEvent::Exited(_status) = &ev {
+            inner_codes
+                .write()
+                .unwrap()
+                .insert(name.to_string(), ev.exit_code());
+        } else {
+            assert_eq!(ev.exit_code(), None);
+        }
+        k(ev)
+    })
+    .expect("run_director failed");
+
+    let codes = codes.read().unwrap();
+    assert_eq!(codes["foo"], None);
+    assert_eq!(codes["bar"], Some(7));
+}
+
+#[test]
+fn test_install_signal_forwarding() {
+    use std::os::unix::process::ExitStatusExt;
+
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.install_signal_forwarding()
+        .expect("install_signal_forwarding failed");
+    man.install_signal_forwarding()
+        .expect("install_signal_forwarding failed");
+    man.spawn("foo".to_string(), Command::new("sleep").arg("100"))
+        .expect("spawn failed");
+
+    unsafe {
+        libc::kill(libc::getpid(), libc::SIGINT);
+    }
+
+    let statuses = man.run_director().expect("run_director failed");
+    assert_eq!(statuses["foo"].signal(), Some(libc::SIGINT));
+}
+
+#[test]
+fn test_close() {
+    let mut man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    for name in ["foo", "bar"] {
+        man.spawn(name.to_string(), Command::new("sleep").arg("100"))
+            .expect("spawn failed");
+    }
+    man.spawn(
+        "stubborn".to_string(),
+        Command::new("sh").arg("-c").arg("trap '' TERM; sleep 100"),
+    )
+    .expect("spawn failed");
+    let pids: Vec<u32> = ["foo", "bar", "stubborn"]
+        .iter()
+        .map(|name| man.pid(name).expect("pid failed"))
+        .collect();
+    let inner = man.clone();
+    let director = thread::spawn(move || inner.run_director());
+    thread::sleep(Duration::from_millis(100));
+
+    let start = std::time::Instant::now();
+    man.close(Duration::from_millis(300)).expect("close failed");
+    assert!(start.elapsed() >= Duration::from_millis(300));
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert!(man.is_empty());
+    for pid in pids {
+        assert_ne!(unsafe { libc::kill(pid as libc::pid_t, 0) }, 0);
+    }
+    director.join().unwrap().expect("run_director failed");
+
+    man.close(Duration::from_millis(300)).expect("close failed");
+}
+
+#[test]
+fn test_close_with_full_queue() {
+    let mut man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_event_capacity(1);
+    man.spawn("foo".to_string(), &mut Command::new("yes"))
+        .expect("spawn failed");
+    let pid = man.pid("foo").expect("pid failed");
+    thread::sleep(Duration::from_millis(50));
+
+    let start = std::time::Instant::now();
+    man.close(Duration::from_millis(100)).expect("close failed");
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert!(man.is_empty());
+    assert_ne!(unsafe { libc::kill(pid as libc::pid_t, 0) }, 0);
+}
+
+#[test]
+fn test_process_groups() {
+    let mut man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_process_groups(true);
+    man.spawn(
+        "foo".to_string(),
+        Command::new("sh")
+            .arg("-c")
+            .arg("sleep 100 & echo $!; wait"),
+    )
+    .expect("spawn failed");
+    let pid = man.pid("foo").expect("pid failed");
+
+    // The grandchild joins the group led by the child.
+    thread::sleep(Duration::from_millis(200));
+    let group = unsafe { libc::getpgid(pid as libc::pid_t) };
+    assert_eq!(group, pid as libc::pid_t);
+
+    assert_eq!(live_group_members(group).len(), 2);
+
+    man.stop_process("foo").expect("stop_process failed");
+    thread::sleep(Duration::from_millis(200));
+    assert!(live_group_members(group).is_empty());
+}
+
+/// Return the pids of the processes in `group` that have not exited, which
+/// excludes zombies that nobody has reaped yet.
+fn live_group_members(group: libc::pid_t) -> Vec<String> {
+    std::fs::read_dir("/proc")
+        .unwrap()
+        .filter_map(|entry| {
+            let pid = entry.ok()?.file_name().into_string().ok()?;
+            let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+            // The fields after the parenthesised command name begin with the
+            // state, the parent pid, and the process group.
+            let fields: Vec<&str> = stat[stat.rfind(')')? + 2..].split(' ').collect();
+            if fields[0] != "Z" && fields[2] == group.to_string() {
+                Some(pid)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_resource_usage() {
+    let mut man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    // Build up a few megabytes in a shell variable, then wait around.
+    man.spawn(
+        "foo".to_string(),
+        Command::new("sh")
+            .arg("-c")
+            .arg("x=$(head -c 4000000 /dev/zero | tr '\\0' a); sleep 100"),
+    )
+    .expect("spawn failed");
+    thread::sleep(Duration::from_millis(500));
+
+    let usage = man.resource_usage("foo").expect("resource_usage failed");
+    assert!(usage.rss_bytes > 0);
+    assert!(usage.cpu_seconds >= 0.0);
+
+    match man.resource_usage("bar") {
+        Err(ManagerError::ProcessUnknown) => {}
+        other => panic!("expected ProcessUnknown, got {:?}", other),
+    }
+
+    man.stop_process("foo").expect("stop_process failed");
+    match man.resource_usage("foo") {
+        Err(ManagerError::ProcessUnknown) => {}
+        other => panic!("expected ProcessUnknown, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_run_process_with_limits() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    let inner = man.clone();
+    thread::spawn(move || {
+        inner.run_process_with_limits(
+            "busy".to_string(),
+            Command::new("sh").arg("-c").arg("while true; do :; done"),
+            ResourceLimits {
+                max_cpu_seconds: Some(1),
+                ..Default::default()
+            },
+            |ev, k: &dyn Fn(ProcessEvent) -> Result<()>| k(ev),
+        )
+    });
+    let inner = man.clone();
+    thread::spawn(move || {
+        inner.run_process_with_limits(
+            "files".to_string(),
+            Command::new("sh").arg("-c").arg("ulimit -n"),
+            ResourceLimits {
+                max_open_files: Some(16),
+                ..Default::default()
+            },
+            |ev, k: &dyn Fn(ProcessEvent) -> Result<()>| k(ev),
+        )
+    });
+    thread::sleep(Duration::from_millis(50));
+
+    let outputs: Arc<RwLock<Vec<u8>>> = Default::default();
+    let inner_outputs = outputs.clone();
+    let statuses = man
+        .run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+            if let ProcessEvent::Output(_handle, bytes) = &ev {
+                inner_outputs.write().unwrap().extend(bytes);
+            }
+            k(ev)
+        })
+        .expect("run_director failed");
+
+    assert_eq!(
+        ExitReason::of(&statuses["busy"]),
+        Some(ExitReason::Signal(libc::SIGXCPU))
+    );
+    assert!(statuses["files"].success());
+    assert_eq!(*outputs.read().unwrap(), b"16\n");
+}
+
+#[test]
+fn test_pre_exec() {
+    let man = unsafe {
+        ProcessManager::new()
+            .with_poll_interval(Duration::from_millis(1))
+            .with_pre_exec(|| {
+                libc::umask(0o077);
+                Ok(())
+            })
+    };
+    man.spawn("foo".to_string(), Command::new("sh").arg("-c").arg("umask"))
+        .expect("spawn failed");
+
+    let outputs: Arc<RwLock<Vec<u8>>> = Default::default();
+    let inner_outputs = outputs.clone();
+    man.run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+        if let ProcessEvent::Output(_handle, bytes) = &ev {
+            inner_outputs.write().unwrap().extend(bytes);
+        }
+        k(ev)
+    })
+    .expect("run_director failed");
+    assert_eq!(*outputs.read().unwrap(), b"0077\n");
+
+    let man = unsafe {
+        ProcessManager::new().with_pre_exec(|| Err(std::io::Error::from_raw_os_error(libc::EPERM)))
+    };
+    match man.spawn("foo".to_string(), &mut Command::new("true")) {
+        Err(ManagerError::SpawnFailed { source, .. }) => {
+            assert_eq!(source.raw_os_error(), Some(libc::EPERM))
+        }
+        other => panic!("expected SpawnFailed, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_pause_resume() {
+    let mut man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    let inner = man.clone();
+    let outputs: Arc<RwLock<usize>> = Default::default();
+    let inner_outputs = outputs.clone();
+    let events: Arc<RwLock<Vec<String>>> = Default::default();
+    let inner_events = events.clone();
+
+    thread::spawn(move || {
+        inner.run_process_with_intercept(
+            "foo".to_string(),
+            Command::new("sh")
+                .arg("-c")
+                .arg("while true; do echo tick; sleep 0.01; done"),
+            move |ev: ProcessEvent, k: &dyn Fn(ProcessEvent) -> Result<()>| {
+                if let ProcessEvent::Output(_handle, _bytes) = &ev {
+                    *inner_outputs.write().unwrap() += 1;
+                }
+                k(ev)
+            },
+        )
+    });
+    thread::sleep(Duration::from_millis(50));
+
+    let inner = man.clone();
+    let director = thread::spawn(move || {
+        inner.run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+            inner_events.write().unwrap().push(format!("{}", ev));
+            k(ev)
+        })
+    });
+
+    thread::sleep(Duration::from_millis(200));
+    man.pause("foo").expect("pause failed");
+    thread::sleep(Duration::from_millis(100));
+    let paused = *outputs.read().unwrap();
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(*outputs.read().unwrap(), paused);
+
+    man.resume("foo").expect("resume failed");
+    thread::sleep(Duration::from_millis(300));
+    assert!(*outputs.read().unwrap() > paused);
+
+    man.stop_process("foo").expect("stop_process failed");
+    director.join().unwrap().expect("run_director failed");
+
+    let events = events.read().unwrap();
+    let paused = events.iter().position(|e| e == "Paused").unwrap();
+    let resumed = events.iter().position(|e| e == "Resumed").unwrap();
+    assert!(paused < resumed);
+}
+
+#[test]
+fn test_pause_with_full_queue() {
+    let mut man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_event_capacity(1);
+    man.spawn("foo".to_string(), &mut Command::new("yes"))
+        .expect("spawn failed");
+
+    let inner = man.clone();
+    let director = thread::spawn(move || inner.run_director());
+    thread::sleep(Duration::from_millis(50));
+
+    let start = std::time::Instant::now();
+    man.pause("foo").expect("pause failed");
+    man.resume("foo").expect("resume failed");
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    man.stop_process("foo").expect("stop_process failed");
+    director.join().unwrap().expect("run_director failed");
+}
+
+#[test]
+fn test_stop_with_full_queue() {
+    let mut man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_event_capacity(1);
+    let inner = man.clone();
+    let monitor =
+        thread::spawn(move || inner.run_process("foo".to_string(), &mut Command::new("yes")));
+    thread::sleep(Duration::from_millis(50));
+
+    // Nothing takes the events of a process that was stopped, so its
+    // monitoring has to give up on a full queue by itself.
+    man.stop_process("foo").expect("stop_process failed");
+    monitor.join().unwrap().expect("run_process failed");
+}
+
+#[test]
+fn test_drop_with_full_queue() {
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_event_capacity(1)
+        .with_kill_on_drop(true);
+    // The interceptor, and the token it holds, go away with the monitoring
+    // thread that runs it.
+    let token = Arc::new(());
+    let inner_token = token.clone();
+    man.run_process_until_ready(
+        "foo".to_string(),
+        &mut Command::new("yes"),
+        regex::Regex::new("^y$").unwrap(),
+        move |ev: ProcessEvent, k: &dyn Fn(ProcessEvent) -> Result<()>| {
+            let _ = &inner_token;
+            k(ev)
+        },
+    )
+    .expect("run_process_until_ready failed");
+    thread::sleep(Duration::from_millis(50));
+
+    drop(man);
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while Arc::strong_count(&token) > 1 {
+        assert!(std::time::Instant::now() < deadline);
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn test_wait_for_process() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn("foo".to_string(), &mut Command::new("true"))
+        .expect("spawn failed");
+
+    let status = man
+        .wait_for_process("foo")
+        .expect("wait_for_process failed");
+    assert!(status.success());
+    assert!(matches!(
+        man.wait_for_process("bar"),
+        Err(ManagerError::ProcessUnknown)
+    ));
+}
+
+#[test]
+fn test_run_process_until_ready() {
+    let mut man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    let ready = regex::Regex::new("^READY$").unwrap();
+
+    let start = std::time::Instant::now();
+    man.run_process_until_ready(
+        "foo".to_string(),
+        Command::new("sh")
+            .arg("-c")
+            .arg("echo starting; sleep 0.2; echo READY; sleep 5"),
+        ready.clone(),
+        |ev, k: &dyn Fn(ProcessEvent) -> Result<()>| k(ev),
+    )
+    .expect("run_process_until_ready failed");
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    assert_eq!(man.status("foo").unwrap(), ProcessStatus::Running);
+    man.stop_process("foo").expect("stop_process failed");
+
+    match man.run_process_until_ready(
+        "bar".to_string(),
+        Command::new("echo").arg("not yet"),
+        ready.clone(),
+        |ev, k: &dyn Fn(ProcessEvent) -> Result<()>| k(ev),
+    ) {
+        Err(ManagerError::NotReady(reason)) => assert_eq!(reason, "process exited"),
+        other => panic!("expected NotReady, got {:?}", other),
+    }
+
+    let mut man = man.with_ready_timeout(Duration::from_millis(100));
+    match man.run_process_until_ready(
+        "baz".to_string(),
+        Command::new("sleep").arg("5"),
+        ready,
+        |ev, k: &dyn Fn(ProcessEvent) -> Result<()>| k(ev),
+    ) {
+        Err(ManagerError::NotReady(reason)) => assert_eq!(reason, "timed out"),
+        other => panic!("expected NotReady, got {:?}", other),
+    }
+    man.stop_process("baz").expect("stop_process failed");
+}
+
+#[test]
+fn test_restart_process() {
+    let mut man = ProcessManager::new();
+    man.spawn("foo".to_string(), Command::new("sleep").arg("5"))
+        .expect("spawn failed");
+    let pid = man.pid("foo").expect("pid failed");
+
+    man.restart_process("foo", Command::new("sleep").arg("5"))
+        .expect("restart_process failed");
+    assert_ne!(man.pid("foo").expect("pid failed"), pid);
+
+    man.stop_process("foo").expect("stop_process failed");
+}
+
+#[test]
+fn test_spawn_shell() {
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_line_buffering(true);
+    man.spawn_shell("x".to_string(), "echo hi && echo bye")
+        .expect("spawn_shell failed");
+    assert_eq!(
+        man.command_line("x").expect("command_line failed"),
+        vec!["sh", "-c", "echo hi && echo bye"]
+    );
+
+    let lines: Arc<RwLock<Vec<String>>> = Default::default();
+    let inner_lines = lines.clone();
+    man.run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+        if let ProcessEvent::Line(HandleType::StdOutput, line) = &ev {
+            inner_lines.write().unwrap().push(line.clone());
+        }
+        k(ev)
+    })
+    .expect("run_director failed");
+    assert_eq!(*lines.read().unwrap(), vec!["hi", "bye"]);
+}
+
+#[test]
+fn test_bytes_read() {
+    let mut man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn(
+        "cat".to_string(),
+        Command::new("sh")
+            .arg("-c")
+            .arg("cat; echo oops >&2; sleep 100"),
+    )
+    .expect("spawn failed");
+    man.send_input("cat", &[b'x'; 100_000])
+        .expect("send_input failed");
+    man.close_stdin("cat").expect("close_stdin failed");
+
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while man.bytes_read("cat").expect("bytes_read failed") != (100_000, 5) {
+        assert!(std::time::Instant::now() < deadline);
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(matches!(
+        man.bytes_read("dog"),
+        Err(ManagerError::ProcessUnknown)
+    ));
+    man.stop_process("cat").expect("stop_process failed");
+}
+
+#[test]
+fn test_command_line() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn(
+        "foo".to_string(),
+        Command::new("echo").arg("hello").arg("world"),
+    )
+    .expect("spawn failed");
+    assert_eq!(
+        man.command_line("foo").expect("command_line failed"),
+        vec!["echo", "hello", "world"]
+    );
+    assert!(matches!(
+        man.command_line("bar"),
+        Err(ManagerError::ProcessUnknown)
+    ));
+    man.run_director().expect("run_director failed");
+}
+
+#[test]
+fn test_replace_process() {
+    let mut man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn("foo".to_string(), Command::new("sleep").arg("100"))
+        .expect("spawn failed");
+    let old = man.pid("foo").expect("pid failed");
+
+    let inner = man.clone();
+    let replacing = thread::spawn(move || {
+        inner.replace_process(
+            "foo",
+            Command::new("sh")
+                .arg("-c")
+                .arg("sleep 0.3; echo READY; exec sleep 100"),
+            regex::Regex::new("^READY$").unwrap(),
+        )
+    });
+    // The name keeps referring to the old process until the new one is
+    // ready, and never goes missing.
+    thread::sleep(Duration::from_millis(150));
+    assert_eq!(man.pid("foo").expect("pid failed"), old);
+    while !replacing.is_finished() {
+        man.pid("foo").expect("pid failed");
+    }
+    replacing.join().unwrap().expect("replace_process failed");
+    assert_ne!(man.pid("foo").expect("pid failed"), old);
+    assert_eq!(man.list(), vec!["foo".to_string()]);
+
+    // A replacement that never becomes ready leaves the old process be.
+    let current = man.pid("foo").expect("pid failed");
+    assert!(matches!(
+        man.replace_process(
+            "foo",
+            Command::new("echo").arg("not yet"),
+            regex::Regex::new("^READY$").unwrap(),
+        ),
+        Err(ManagerError::NotReady(_))
+    ));
+    assert_eq!(man.pid("foo").expect("pid failed"), current);
+    assert_eq!(man.list(), vec!["foo".to_string()]);
+
+    man.stop_all();
+}
+
+#[test]
+fn test_replace_process_name() {
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_line_buffering(true)
+        .with_name_prefix(true);
+    man.spawn("foo".to_string(), Command::new("sleep").arg("100"))
+        .expect("spawn failed");
+    man.replace_process(
+        "foo",
+        Command::new("sh")
+            .arg("-c")
+            .arg("echo READY; sleep 0.2; echo after"),
+        // Lines are seen with the prefix added.
+        regex::Regex::new("READY$").unwrap(),
+    )
+    .expect("replace_process failed");
+
+    // Once swapped in, the replacement goes by the name it replaced.
+    let lines: Arc<RwLock<Vec<String>>> = Default::default();
+    let inner_lines = lines.clone();
+    man.run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+        if let ProcessEvent::Line(_handle, line) = &ev {
+            inner_lines.write().unwrap().push(line.clone());
+        }
+        k(ev)
+    })
+    .expect("run_director failed");
+    assert_eq!(lines.read().unwrap().last().unwrap(), "[foo] after");
+}
+
+#[test]
+fn test_restart_policy() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    let inner = man.clone();
+    let events: Arc<RwLock<Vec<String>>> = Default::default();
+    let inner_events = events.clone();
+
+    thread::spawn(move || {
+        inner.run_process_with_policy(
+            "foo".to_string(),
+            Command::new("sh").arg("-c").arg("exit 1"),
+            RestartPolicy::OnFailure { max: 2 },
+            move |ev: ProcessEvent, k: &dyn Fn(ProcessEvent) -> Result<()>| {
+                match &ev {
+                    ProcessEvent::Restarted { .. } | ProcessEvent::Exited(_) => {
+                        inner_events.write().unwrap().push(format!("{}", ev))
+                    }
+                    _ => (),
+                }
+                k(ev)
+            },
+        )
+    });
+    thread::sleep(Duration::from_millis(50));
+    man.run_director().expect("run_director failed");
+
+    assert_eq!(
+        *events.read().unwrap(),
+        vec![
+            "Restarted(1, 0ns)",
+            "Restarted(2, 0ns)",
+            "Exited(exit status: 1)"
+        ]
+    );
+}
+
+#[test]
+fn test_watchdog() {
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_watchdog(Duration::from_millis(200));
+    let inner = man.clone();
+    let events: Arc<RwLock<Vec<String>>> = Default::default();
+    let inner_events = events.clone();
+
+    let start = std::time::Instant::now();
+    thread::spawn(move || {
+        inner.run_process_with_policy(
+            "foo".to_string(),
+            Command::new("sh")
+                .arg("-c")
+                .arg("for i in 1 2 3; do echo tick; sleep 0.1; done; exec sleep 100"),
+            RestartPolicy::OnFailure { max: 1 },
+            move |ev: ProcessEvent, k: &dyn Fn(ProcessEvent) -> Result<()>| {
+                match &ev {
+                    ProcessEvent::WatchdogTripped
+                    | ProcessEvent::Restarted { .. }
+                    | ProcessEvent::Exited(_) => {
+                        inner_events.write().unwrap().push(format!("{}", ev))
+                    }
+                    _ => (),
+                }
+                k(ev)
+            },
+        )
+    });
+    thread::sleep(Duration::from_millis(50));
+    man.run_director().expect("run_director failed");
+
+    // It stays quiet for the idle window only after its last tick, twice.
+    assert!(start.elapsed() >= Duration::from_millis(800));
+    assert_eq!(
+        *events.read().unwrap(),
+        vec![
+            "WatchdogTripped",
+            "Restarted(1, 0ns)",
+            "WatchdogTripped",
+            "Exited(signal: 9 (SIGKILL))"
+        ]
+    );
+}
+
+#[test]
+fn test_run_process_with_predicate() {
+    let path = std::env::temp_dir().join(format!("procman-runs-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    let inner = man.clone();
+    let events: Arc<RwLock<Vec<String>>> = Default::default();
+    let inner_events = events.clone();
+
+    // Exits with 0, then 2, then 1, counting its runs in a file.
+    let script = format!(
+        "echo x >> {0}; case $(wc -l < {0}) in *1) exit 0;; *2) exit 2;; *) exit 1;; esac",
+        path.display()
+    );
+    thread::spawn(move || {
+        inner.run_process_with_predicate(
+            "foo".to_string(),
+            Command::new("sh").arg("-c").arg(script),
+            |status, _attempts| status.code().is_some_and(|code| code % 2 == 0),
+            move |ev: ProcessEvent, k: &dyn Fn(ProcessEvent) -> Result<()>| {
+                match &ev {
+                    ProcessEvent::Restarted { .. } | ProcessEvent::Exited(_) => {
+                        inner_events.write().unwrap().push(format!("{}", ev))
+                    }
+                    _ => (),
+                }
+                k(ev)
+            },
+        )
+    });
+    thread::sleep(Duration::from_millis(50));
+    man.run_director().expect("run_director failed");
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+        *events.read().unwrap(),
+        vec![
+            "Restarted(1, 0ns)",
+            "Restarted(2, 0ns)",
+            "Exited(exit status: 1)"
+        ]
+    );
+}
+
+#[test]
+fn test_run_process_with_factory() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    let inner = man.clone();
+    let output: Arc<RwLock<Vec<u8>>> = Default::default();
+    let inner_output = output.clone();
+
+    thread::spawn(move || {
+        inner.run_process_with_factory(
+            "foo".to_string(),
+            |attempt| {
+                let mut command = Command::new("sh");
+                command
+                    .arg("-c")
+                    .arg("echo $ATTEMPT; exit 1")
+                    .env("ATTEMPT", attempt.to_string());
+                command
+            },
+            RestartPolicy::OnFailure { max: 2 },
+            move |ev: ProcessEvent, k: &dyn Fn(ProcessEvent) -> Result<()>| {
+                if let ProcessEvent::Output(_handle, bytes) = &ev {
+                    inner_output.write().unwrap().extend(bytes);
+                }
+                k(ev)
+            },
+        )
+    });
+    thread::sleep(Duration::from_millis(50));
+    man.run_director().expect("run_director failed");
+
+    assert_eq!(*output.read().unwrap(), b"0\n1\n2\n");
+}
+
+#[test]
+fn test_restart_backoff() {
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_restart_backoff(Duration::from_millis(10), Duration::from_secs(1));
+    let inner = man.clone();
+    let delays: Arc<RwLock<Vec<Duration>>> = Default::default();
+    let inner_delays = delays.clone();
+
+    thread::spawn(move || {
+        inner.run_process_with_policy(
+            "foo".to_string(),
+            &mut Command::new("false"),
+            RestartPolicy::OnFailure { max: 3 },
+            move |ev: ProcessEvent, k: &dyn Fn(ProcessEvent) -> Result<()>| {
+                if let ProcessEvent::Restarted { delay, .. } = &ev {
+                    inner_delays.write().unwrap().push(*delay)
+                }
+                k(ev)
+            },
+        )
+    });
+    thread::sleep(Duration::from_millis(50));
+    man.run_director().expect("run_director failed");
+
+    assert_eq!(
+        *delays.read().unwrap(),
+        vec![
+            Duration::from_millis(10),
+            Duration::from_millis(20),
+            Duration::from_millis(40)
+        ]
+    );
+}
+
+#[test]
+fn test_stop_process_graceful() {
+    let man = ProcessManager::new();
+    man.spawn(
+        "trapper".to_string(),
+        Command::new("sh")
+            .arg("-c")
+            .arg("trap 'kill $!; exit 0' TERM; sleep 10 & wait"),
+    )
+    .expect("spawn failed");
+    man.spawn(
+        "stubborn".to_string(),
+        Command::new("sh").arg("-c").arg("trap '' TERM; sleep 10"),
+    )
+    .expect("spawn failed");
+    thread::sleep(Duration::from_millis(300));
+
+    assert_eq!(
+        man.stop_process_graceful("trapper", Duration::from_secs(5))
+            .expect("stop_process_graceful failed"),
+        StopOutcome::Graceful
+    );
+    assert_eq!(
+        man.stop_process_graceful("stubborn", Duration::from_millis(200))
+            .expect("stop_process_graceful failed"),
+        StopOutcome::Killed
+    );
+    assert!(matches!(
+        man.stop_process_graceful("trapper", Duration::from_millis(200)),
+        Err(ManagerError::ProcessUnknown)
+    ));
+}
+
+#[test]
+fn test_kill_timeout() {
+    let mut man = ProcessManager::new().with_kill_timeout(Duration::from_secs(1));
+    man.spawn("foo".to_string(), Command::new("sleep").arg("100"))
+        .expect("spawn failed");
+
+    let start = std::time::Instant::now();
+    man.stop_process("foo").expect("stop_process failed");
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert!(matches!(
+        man.stop_process("foo"),
+        Err(ManagerError::ProcessUnknown)
+    ));
+}
+
+#[test]
+fn test_stop_all() {
+    let mut man = ProcessManager::new();
+    assert!(man.stop_all().is_empty());
+
+    for name in &["a", "b", "c"] {
+        man.spawn(name.to_string(), Command::new("sleep").arg("10"))
+            .expect("spawn failed");
+    }
+
+    let mut results = man.stop_all();
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+    let names: Vec<&str> = results.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, vec!["a", "b", "c"]);
+    assert!(results.iter().all(|(_, result)| result.is_ok()));
+    for name in &["a", "b", "c"] {
+        assert!(matches!(man.pid(name), Err(ManagerError::ProcessUnknown)));
+    }
+}
+
+#[test]
+fn test_detach() {
+    let mut man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn("foo".to_string(), Command::new("sleep").arg("100"))
+        .expect("spawn failed");
+    let pid = man.pid("foo").expect("pid failed");
+
+    let mut child = man.detach("foo").expect("detach failed");
+    assert!(man.list().is_empty());
+    assert_eq!(child.id(), pid);
+    assert!(matches!(
+        man.detach("foo"),
+        Err(ManagerError::ProcessUnknown)
+    ));
+
+    thread::sleep(Duration::from_millis(50));
+    assert!(child.try_wait().expect("try_wait failed").is_none());
+    child.kill().expect("kill failed");
+    child.wait().expect("wait failed");
+}
+
+#[test]
+fn test_detach_with_full_queue() {
+    let mut man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_event_capacity(1);
+    man.spawn("foo".to_string(), &mut Command::new("yes"))
+        .expect("spawn failed");
+    thread::sleep(Duration::from_millis(50));
+
+    let mut child = man.detach("foo").expect("detach failed");
+    child.kill().expect("kill failed");
+    child.wait().expect("wait failed");
+}
+
+#[test]
+fn test_list() {
+    let mut man = ProcessManager::new();
+    assert!(man.list().is_empty());
+
+    for name in &["a", "b"] {
+        man.spawn(name.to_string(), Command::new("sleep").arg("10"))
+            .expect("spawn failed");
+    }
+
+    let names: HashSet<String> = man.list().into_iter().collect();
+    let expected: HashSet<String> = ["a", "b"].iter().map(|s| s.to_string()).collect();
+    assert_eq!(names, expected);
+    man.stop_all();
+}
+
+#[test]
+fn test_len() {
+    let mut man = ProcessManager::new();
+    assert_eq!(man.len(), 0);
+    assert!(man.is_empty());
+
+    for name in &["a", "b"] {
+        man.spawn(name.to_string(), Command::new("sleep").arg("10"))
+            .expect("spawn failed");
+    }
+    assert_eq!(man.len(), 2);
+    assert!(!man.is_empty());
+
+    man.stop_all();
+    assert!(man.is_empty());
+}
+
+#[test]
+fn test_status() {
+    let man = ProcessManager::new();
+    man.spawn("foo".to_string(), Command::new("sleep").arg("1"))
+        .expect("spawn failed");
+
+    assert_eq!(man.status("foo").unwrap(), ProcessStatus::Running);
+    assert_eq!(man.status("foo").unwrap(), ProcessStatus::Running);
+    assert!(matches!(
+        man.status("bar"),
+        Err(ManagerError::ProcessUnknown)
+    ));
+
+    let mut status = ProcessStatus::Running;
+    for _ in 0..50 {
+        status = man.status("foo").unwrap();
+        if status != ProcessStatus::Running {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    match status {
+        ProcessStatus::Exited(status) => assert!(status.success()),
+        other => panic!("expected Exited, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_director_exit_statuses() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn("ok".to_string(), &mut Command::new("true"))
+        .expect("spawn failed");
+    man.spawn(
+        "fail".to_string(),
+        Command::new("sh").arg("-c").arg("exit 1"),
+    )
+    .expect("spawn failed");
+
+    let statuses = man.run_director().expect("run_director failed");
+    assert_eq!(statuses.len(), 2);
+    assert!(statuses["ok"].success());
+    assert_eq!(statuses["fail"].code(), Some(1));
+}
+
+#[test]
+fn test_event_capacity() {
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_line_buffering(true)
+        .with_event_capacity(2);
+    let inner = man.clone();
+    let produced: Arc<RwLock<usize>> = Default::default();
+    let inner_produced = produced.clone();
+
+    thread::spawn(move || {
+        inner.run_process_with_intercept(
+            "foo".to_string(),
+            Command::new("seq").arg("10"),
+            move |ev: ProcessEvent, k: &dyn Fn(ProcessEvent) -> Result<()>| {
+                if let ProcessEvent::Line(_handle, _line) = &ev {
+                    *inner_produced.write().unwrap() += 1;
+                }
+                k(ev)
+            },
+        )
+    });
+
+    // With nobody draining the queue, the producer stalls once it is full:
+    // two events queued, and a third waiting to be sent.
+    thread::sleep(Duration::from_millis(300));
+    assert!(*produced.read().unwrap() <= 3);
+
+    man.run_director().expect("run_director failed");
+    assert_eq!(*produced.read().unwrap(), 10);
+}
+
+#[test]
+fn test_total_event_memory() {
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_total_event_memory(64)
+        .with_overflow_policy(OverflowPolicy::DropNewest);
+    for name in &["a", "b", "c"] {
+        man.spawn(name.to_string(), Command::new("seq").arg("1000"))
+            .expect("spawn failed");
+    }
+    thread::sleep(Duration::from_millis(300));
+
+    let dropped: Arc<RwLock<usize>> = Default::default();
+    let inner_dropped = dropped.clone();
+    let statuses = man
+        .run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+            if let ProcessEvent::Dropped(count) = &ev {
+                *inner_dropped.write().unwrap() += count;
+            }
+            k(ev)
+        })
+        .expect("run_director failed");
+    assert_eq!(statuses.len(), 3);
+    assert!(*dropped.read().unwrap() > 0);
+}
+
+#[test]
+fn test_overflow_policy() {
+    let man = ProcessManager::new().with_overflow_policy(OverflowPolicy::DropOldest);
+    assert!(matches!(
+        man.spawn("foo".to_string(), &mut Command::new("true")),
+        Err(ManagerError::InvalidConfig(_))
+    ));
+
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_line_buffering(true)
+        .with_event_capacity(1)
+        .with_overflow_policy(OverflowPolicy::DropOldest);
+    man.spawn("foo".to_string(), Command::new("seq").arg("1000"))
+        .expect("spawn failed");
+    thread::sleep(Duration::from_millis(300));
+
+    let dropped: Arc<RwLock<usize>> = Default::default();
+    let inner_dropped = dropped.clone();
+    man.run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+        if let ProcessEvent::Dropped(count) = &ev {
+            *inner_dropped.write().unwrap() += count;
+        }
+        k(ev)
+    })
+    .expect("run_director failed");
+    assert!(*dropped.read().unwrap() > 0);
+}
+
+#[test]
+fn test_director_does_not_lock_table() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    let inner = man.clone();
+    man.spawn("foo".to_string(), Command::new("echo").arg("hello"))
+        .expect("spawn failed");
+
+    // The callback can use the rest of the manager while events are being
+    // delivered.
+    let seen: Arc<RwLock<Vec<Vec<String>>>> = Default::default();
+    let inner_seen = seen.clone();
+    man.run_director_with_intercept(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+        inner_seen.write().unwrap().push(inner.list());
+        k(ev)
+    })
+    .expect("run_director failed");
+
+    assert!(seen
+        .read()
+        .unwrap()
+        .iter()
+        .all(|names| names == &vec!["foo".to_string()]));
+}
+
+#[test]
+fn test_join_all() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn("ok".to_string(), &mut Command::new("true"))
+        .expect("spawn failed");
+    man.spawn(
+        "fail".to_string(),
+        Command::new("sh").arg("-c").arg("exit 2"),
+    )
+    .expect("spawn failed");
+
+    let statuses = man.join_all();
+    assert_eq!(statuses.len(), 2);
+    assert!(statuses["ok"].success());
+    assert_eq!(statuses["fail"].code(), Some(2));
+    assert!(man.is_empty());
+    assert!(man.join_all().is_empty());
+}
+
+#[test]
+fn test_try_recv_event() {
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_handles(HandleSet::NONE);
+    assert!(man.try_recv_event().is_none());
+
+    man.spawn("foo".to_string(), &mut Command::new("true"))
+        .expect("spawn failed");
+    man.wait_for_process("foo")
+        .expect("wait_for_process failed");
+    thread::sleep(Duration::from_millis(50));
+
+    match man.try_recv_event() {
+        Some((name, ProcessEvent::Exited(status))) => {
+            assert_eq!(name, "foo");
+            assert!(status.success());
+        }
+        other => panic!("expected Exited, got {:?}", other),
+    }
+    assert!(man.try_recv_event().is_none());
+    assert!(man.is_empty());
+}
+
+#[test]
+fn test_events() {
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_handles(HandleSet::STDOUT)
+        .with_coalesce(true);
+    man.spawn("foo".to_string(), Command::new("echo").arg("foo"))
+        .expect("spawn failed");
+    man.wait_for_process("foo")
+        .expect("wait_for_process failed");
+    thread::sleep(Duration::from_millis(50));
+
+    let events: Vec<String> = man
+        .events()
+        .map(|(name, ev)| format!("{}: {}", name, ev))
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            "foo: Output(StdOutput, \"foo\\n\", 4)",
+            "foo: Eof(StdOutput)",
+            "foo: Exited(exit status: 0)",
+        ]
+    );
+    assert!(man.events().next().is_none());
+}
+
+#[test]
+fn test_wait_all_with_timeout() {
+    let mut man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn("quick".to_string(), &mut Command::new("true"))
+        .expect("spawn failed");
+    man.spawn("slow".to_string(), Command::new("sleep").arg("100"))
+        .expect("spawn failed");
+
+    let start = std::time::Instant::now();
+    let (statuses, running) = man.wait_all_with_timeout(Duration::from_millis(200));
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(statuses.len(), 1);
+    assert!(statuses["quick"].success());
+    assert_eq!(running, vec!["slow".to_string()]);
+
+    man.stop_all();
+}
+
+#[test]
+fn test_exit_hook() {
+    let exits: Arc<RwLock<Vec<String>>> = Default::default();
+    let inner_exits = exits.clone();
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_exit_hook(move |name, status| {
+            inner_exits
+                .write()
+                .unwrap()
+                .push(format!("{}: {}", name, status))
+        });
+    man.spawn(
+        "foo".to_string(),
+        Command::new("sh").arg("-c").arg("exit 3"),
+    )
+    .expect("spawn failed");
+
+    man.run_director().expect("run_director failed");
+    assert!(man.is_empty());
+    assert_eq!(*exits.read().unwrap(), vec!["foo: exit status: 3"]);
+}
+
+#[test]
+fn test_any_failed() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn("ok".to_string(), &mut Command::new("true"))
+        .expect("spawn failed");
+    man.run_director().expect("run_director failed");
+    assert!(!man.any_failed());
+
+    man.spawn("bad".to_string(), &mut Command::new("false"))
+        .expect("spawn failed");
+    man.run_director().expect("run_director failed");
+    assert!(man.any_failed());
+}
+
+#[test]
+fn test_run_director_with_filter() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    let received = man.subscribe();
+    man.spawn("foo".to_string(), Command::new("echo").arg("hello"))
+        .expect("spawn failed");
+
+    let statuses = man
+        .run_director_with_filter(|_name, ev| match ev {
+            ProcessEvent::Output(handle, bytes) => {
+                Some(ProcessEvent::Output(handle, bytes.to_ascii_uppercase()))
+            }
+            ProcessEvent::Eof(_handle) => None,
+            ev => Some(ev),
+        })
+        .expect("run_director failed");
+    assert!(statuses["foo"].success());
+
+    let events: Vec<String> = received
+        .try_iter()
+        .map(|(_name, ev)| ev.to_string())
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            "Output(StdOutput, \"HELLO\\n\", 6)",
+            "Exited(exit status: 0)"
+        ]
+    );
+}
+
+#[test]
+fn test_outcomes() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn("ok".to_string(), &mut Command::new("true"))
+        .expect("spawn failed");
+    man.spawn(
+        "failed".to_string(),
+        Command::new("sh").arg("-c").arg("exit 3"),
+    )
+    .expect("spawn failed");
+    man.spawn(
+        "killed".to_string(),
+        Command::new("sh").arg("-c").arg("kill -KILL $$"),
+    )
+    .expect("spawn failed");
+    assert!(man.outcomes().is_empty());
+
+    man.join_all();
+    let outcomes = man.outcomes();
+    assert_eq!(outcomes.len(), 3);
+    assert_eq!(outcomes["ok"], Outcome::Success);
+    assert_eq!(outcomes["failed"], Outcome::Failed(3));
+    assert_eq!(outcomes["killed"], Outcome::Killed(libc::SIGKILL));
+}
+
+#[test]
+fn test_run_director_blocking_until_cancel() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    let received = man.subscribe();
+    let token = CancelToken::new();
+    let (inner, inner_token) = (man.clone(), token.clone());
+    let director = thread::spawn(move || inner.run_director_blocking_until_cancel(inner_token));
+
+    // The director is already waiting, with nothing to direct yet.
+    thread::sleep(Duration::from_millis(50));
+    assert!(!director.is_finished());
+    man.spawn("foo".to_string(), Command::new("echo").arg("hello"))
+        .expect("spawn failed");
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while !man.is_empty() {
+        assert!(std::time::Instant::now() < deadline);
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    token.cancel();
+    let statuses = director.join().unwrap().expect("run_director failed");
+    assert!(statuses["foo"].success());
+    assert!(received.try_iter().any(|(name, ev)| name == "foo"
+        && matches!(ev, ProcessEvent::Output(_handle, bytes) if bytes == b"hello\n")));
+}
+
+#[test]
+fn test_flush_events() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    let received = man.subscribe();
+    man.spawn("foo".to_string(), Command::new("echo").arg("hello"))
+        .expect("spawn failed");
+    man.wait_for_process("foo")
+        .expect("wait_for_process failed");
+    thread::sleep(Duration::from_millis(100));
+
+    // Cancelled before it even starts, the director still delivers what the
+    // exited process left behind.
+    let token = CancelToken::new();
+    token.cancel();
+    let statuses = man.run_director_until(token).expect("run_director failed");
+    assert!(statuses["foo"].success());
+    assert!(man.is_empty());
+    let outputs: Vec<String> = received
+        .try_iter()
+        .filter_map(|(name, ev)| match ev {
+            ProcessEvent::Output(_handle, bytes) => {
+                Some(format!("{}: {}", name, String::from_utf8_lossy(&bytes)))
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(outputs, vec!["foo: hello\n"]);
+
+    man.spawn("bar".to_string(), Command::new("echo").arg("hello"))
+        .expect("spawn failed");
+    man.wait_for_process("bar")
+        .expect("wait_for_process failed");
+    thread::sleep(Duration::from_millis(100));
+    let names: Arc<RwLock<HashSet<String>>> = Default::default();
+    let inner_names = names.clone();
+    let statuses = man.flush_events(move |name, ev, k: &mut dyn FnMut(ProcessEvent)| {
+        inner_names.write().unwrap().insert(name.to_string());
+        k(ev)
+    });
+    assert!(statuses["bar"].success());
+    assert_eq!(*names.read().unwrap(), HashSet::from(["bar".to_string()]));
+}
+
+#[test]
+fn test_drain() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn("foo".to_string(), Command::new("echo").arg("hello"))
+        .expect("spawn failed");
+    man.wait_for_process("foo")
+        .expect("wait_for_process failed");
+    thread::sleep(Duration::from_millis(100));
+
+    let events: Arc<RwLock<Vec<String>>> = Default::default();
+    let inner_events = events.clone();
+    let statuses = man.drain(move |ev, k: &mut dyn FnMut(ProcessEvent)| {
+        inner_events.write().unwrap().push(format!("{}", ev));
+        k(ev)
+    });
+    assert!(statuses["foo"].success());
+    assert!(man.is_empty());
+
+    let mut events = events.read().unwrap().clone();
+    assert_eq!(events.pop().unwrap(), "Exited(exit status: 0)");
+    events.sort();
+    assert_eq!(
+        events,
+        vec![
+            "Eof(StdError)",
+            "Eof(StdOutput)",
+            "Output(StdOutput, \"hello\\n\", 6)"
+        ]
+    );
+
+    let statuses = man.drain(|_ev, _k: &mut dyn FnMut(ProcessEvent)| panic!("unexpected event"));
+    assert!(statuses.is_empty());
+}
+
+#[test]
+fn test_run_director_until() {
+    let mut man = ProcessManager::new();
+    man.spawn("foo".to_string(), Command::new("sleep").arg("100"))
+        .expect("spawn failed");
+
+    let token = CancelToken::new();
+    let inner = man.clone();
+    let inner_token = token.clone();
+    let director = thread::spawn(move || inner.run_director_until(inner_token));
+
+    thread::sleep(Duration::from_millis(50));
+    let start = std::time::Instant::now();
+    token.cancel();
+    let statuses = director.join().unwrap().expect("run_director_until failed");
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert!(statuses.is_empty());
+
+    assert_eq!(man.list(), vec!["foo".to_string()]);
+    man.stop_all();
+}
+
+#[test]
+fn test_run_director_with_timeout() {
+    let mut man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn("foo".to_string(), Command::new("sleep").arg("100"))
+        .expect("spawn failed");
+    man.spawn("bar".to_string(), &mut Command::new("true"))
+        .expect("spawn failed");
+
+    let start = std::time::Instant::now();
+    match man
+        .run_director_with_timeout(Duration::from_millis(100))
+        .expect("run_director_with_timeout failed")
+    {
+        DirectorOutcome::TimedOut { statuses, running } => {
+            assert!(statuses["bar"].success());
+            assert_eq!(running, vec!["foo".to_string()]);
+        }
+        other => panic!("expected TimedOut, got {:?}", other),
+    }
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(man.list(), vec!["foo".to_string()]);
+
+    man.stop_all();
+    match man
+        .run_director_with_timeout(Duration::from_secs(10))
+        .expect("run_director_with_timeout failed")
+    {
+        DirectorOutcome::Finished(statuses) => assert!(statuses.is_empty()),
+        other => panic!("expected Finished, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_merged_output() {
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_merged_output(true);
+    let inner = man.clone();
+    let output: Arc<RwLock<Vec<u8>>> = Default::default();
+    let inner_output = output.clone();
+
+    thread::spawn(move || {
+        inner.run_process_with_intercept(
+            "foo".to_string(),
+            Command::new("sh").arg("-c").arg("echo out; echo err >&2"),
+            move |ev: ProcessEvent, k: &dyn Fn(ProcessEvent) -> Result<()>| {
+                match &ev {
+                    ProcessEvent::Output(HandleType::StdOutput, bytes) => {
+                        inner_output.write().unwrap().extend_from_slice(bytes)
+                    }
+                    ProcessEvent::Output(handle, _bytes) => panic!("output on {:?}", handle),
+                    _ => (),
+                }
+                k(ev)
+            },
+        )
+    });
+    thread::sleep(Duration::from_millis(50));
+    man.run_director().expect("run_director failed");
+
+    let output = String::from_utf8(output.read().unwrap().clone()).unwrap();
+    assert!(output.contains("out\n"));
+    assert!(output.contains("err\n"));
+}
+
+#[test]
+fn test_run_process_with_output() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    let inner = man.clone();
+    let events: Arc<RwLock<Vec<String>>> = Default::default();
+    let inner_events = events.clone();
+    let path = std::env::temp_dir().join(format!("procman-output-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let target = OutputTarget::File(path.clone());
+
+    thread::spawn(move || {
+        inner.run_process_with_output(
+            "foo".to_string(),
+            Command::new("sh")
+                .arg("-c")
+                .arg("seq 1 10000; echo err >&2"),
+            target,
+            OutputTarget::Events,
+            move |ev: ProcessEvent, k: &dyn Fn(ProcessEvent) -> Result<()>| {
+                inner_events.write().unwrap().push(format!("{}", ev));
+                k(ev)
+            },
+        )
+    });
+    thread::sleep(Duration::from_millis(50));
+    man.run_director().expect("run_director failed");
+
+    let events = events.read().unwrap();
+    assert!(!events.iter().any(|e| e.starts_with("Output(StdOutput")));
+    assert!(events.iter().any(|e| e.starts_with("Output(StdError")));
+    assert!(!events.iter().any(|e| e == "Eof(StdOutput)"));
+
+    let contents = std::fs::read_to_string(&path).expect("read failed");
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(contents.lines().count(), 10000);
+    assert_eq!(contents.lines().last(), Some("10000"));
+}
+
+#[test]
+fn test_tee_output() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    let inner = man.clone();
+    let output: Arc<RwLock<Vec<u8>>> = Default::default();
+    let inner_output = output.clone();
+    let path = std::env::temp_dir().join(format!("procman-tee-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let target = OutputTarget::Tee(path.clone());
+
+    thread::spawn(move || {
+        inner.run_process_with_output(
+            "foo".to_string(),
+            Command::new("seq").arg("1").arg("1000"),
+            target,
+            OutputTarget::Events,
+            move |ev: ProcessEvent, k: &dyn Fn(ProcessEvent) -> Result<()>| {
+                if let ProcessEvent::Output(HandleType::StdOutput, bytes) = &ev {
+                    inner_output.write().unwrap().extend_from_slice(bytes);
+                }
+                k(ev)
+            },
+        )
+    });
+    thread::sleep(Duration::from_millis(50));
+    man.run_director().expect("run_director failed");
+
+    let contents = std::fs::read(&path).expect("read failed");
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(contents.split(|b| *b == b'\n').count(), 1001);
+    assert_eq!(contents, *output.read().unwrap());
+}
+
+#[test]
+fn test_output_target_open_failed() {
+    let path = std::env::temp_dir()
+        .join(format!("procman-no-such-dir-{}", std::process::id()))
+        .join("output");
+    for target in [OutputTarget::File(path.clone()), OutputTarget::Tee(path)] {
+        let man = ProcessManager::new();
+        match man.run_process_with_output(
+            "foo".to_string(),
+            Command::new("echo").arg("hello"),
+            target,
+            OutputTarget::Events,
+            |ev, k: &dyn Fn(ProcessEvent) -> Result<()>| k(ev),
+        ) {
+            Err(ManagerError::SpawnFailed { name, source }) => {
+                assert_eq!(name, "foo");
+                assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+            }
+            other => panic!("expected SpawnFailed, got {:?}", other),
+        }
+        assert!(man.is_empty());
+    }
+}
+
+#[test]
+fn test_output_history() {
+    let mut man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_output_history(10);
+    man.spawn(
+        "foo".to_string(),
+        Command::new("sh")
+            .arg("-c")
+            .arg("seq 1 100; printf partial; sleep 5"),
+    )
+    .expect("spawn failed");
+    assert!(man.recent_output("foo").unwrap().is_empty());
+
+    thread::sleep(Duration::from_millis(500));
+    let expected: Vec<String> = (91..=100).map(|i| i.to_string()).collect();
+    assert_eq!(man.recent_output("foo").unwrap(), expected);
+    assert!(matches!(
+        man.recent_output("bar"),
+        Err(ManagerError::ProcessUnknown)
+    ));
+
+    man.stop_process("foo").expect("stop_process failed");
+}
+
+#[test]
+fn test_handles() {
+    assert_eq!(HandleSet::default(), HandleSet::STDOUT | HandleSet::STDERR);
+
+    let man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_handles(HandleSet::STDOUT);
+    let events = man.subscribe();
+    man.spawn(
+        "foo".to_string(),
+        Command::new("sh").arg("-c").arg("echo out; echo err >&2"),
+    )
+    .expect("spawn failed");
+    man.run_director().expect("run_director failed");
+
+    let events: Vec<String> = events
+        .try_iter()
+        .map(|(_name, ev)| ev.to_string())
+        .collect();
+    assert!(events.contains(&"Output(StdOutput, \"out\\n\", 4)".to_string()));
+    assert!(!events.iter().any(|e| e.contains("StdError")));
+}
+
+#[test]
+fn test_run_and_capture() {
+    let result = ProcessManager::run_and_capture(Command::new("echo").arg("hi"))
+        .expect("run_and_capture failed");
+    assert_eq!(result.stdout, b"hi\n");
+    assert!(result.stderr.is_empty());
+    assert!(result.status.success());
+}
+
+#[test]
+fn test_mock_clock() {
+    let clock = MockClock::new();
+    let began = clock.now();
+
+    // Sleeping waits for the clock to be advanced far enough.
+    let inner = clock.clone();
+    let sleeper = thread::spawn(move || inner.sleep(Duration::from_secs(10)));
+    thread::sleep(Duration::from_millis(50));
+    clock.advance(Duration::from_secs(5));
+    thread::sleep(Duration::from_millis(50));
+    assert!(!sleeper.is_finished());
+    clock.advance(Duration::from_secs(5));
+    sleeper.join().unwrap();
+    assert_eq!(clock.now() - began, Duration::from_secs(10));
+
+    let mut man = ProcessManager::new().with_clock(clock.clone());
+    let inner = man.clone();
+    let events: Arc<RwLock<Vec<String>>> = Default::default();
+    let inner_events = events.clone();
+
+    // Keep time going by a minute at a time until the test is done with it.
+    let done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let ticker = {
+        let clock = clock.clone();
+        let done = done.clone();
+        thread::spawn(move || {
+            while !done.load(std::sync::atomic::Ordering::SeqCst) {
+                clock.advance(Duration::from_secs(60));
+                thread::sleep(Duration::from_millis(1));
+            }
+        })
+    };
+
+    // An hour goes by on the clock without anyone waiting for it.
+    let start = std::time::Instant::now();
+    let began = clock.now();
+    thread::spawn(move || {
+        inner.run_process_with_timeout(
+            "foo".to_string(),
+            Command::new("sleep").arg("100"),
+            Duration::from_secs(3600),
+            move |ev: ProcessEvent, k: &dyn Fn(ProcessEvent) -> Result<()>| {
+                inner_events.write().unwrap().push(format!("{}", ev));
+                k(ev)
+            },
+        )
+    });
+    thread::sleep(Duration::from_millis(50));
+    man.run_director().expect("run_director failed");
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(clock.now() - began >= Duration::from_secs(3600));
+    assert!(events.read().unwrap().contains(&"TimedOut".to_string()));
+
+    man.spawn("bar".to_string(), Command::new("sleep").arg("100"))
+        .expect("spawn failed");
+    let began = clock.now();
+    thread::sleep(Duration::from_millis(50));
+    let elapsed = clock.now() - began;
+    assert!(elapsed >= Duration::from_secs(60));
+    assert!(man.uptime("bar").expect("uptime failed") >= elapsed);
+    man.stop_all();
+    done.store(true, std::sync::atomic::Ordering::SeqCst);
+    ticker.join().unwrap();
+}
+
+#[test]
+fn test_run_process_with_timeout() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(10));
+    let inner = man.clone();
+    let events: Arc<RwLock<Vec<String>>> = Default::default();
+    let inner_events = events.clone();
+
+    let start = std::time::Instant::now();
+    thread::spawn(move || {
+        inner.run_process_with_timeout(
+            "foo".to_string(),
+            Command::new("sh")
+                .arg("-c")
+                .arg("echo started; exec sleep 10"),
+            Duration::from_millis(200),
+            move |ev: ProcessEvent, k: &dyn Fn(ProcessEvent) -> Result<()>| {
+                inner_events.write().unwrap().push(format!("{}", ev));
+                k(ev)
+            },
+        )
+    });
+    thread::sleep(Duration::from_millis(50));
+    man.run_director().expect("run_director failed");
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    let events = events.read().unwrap();
+    assert!(events.contains(&"Output(StdOutput, \"started\\n\", 8)".to_string()));
+    let timed_out = events.iter().position(|e| e == "TimedOut").unwrap();
+    let exited = events.iter().position(|e| e.starts_with("Exited")).unwrap();
+    assert!(timed_out < exited);
+    assert!(man.list().is_empty());
+}
+
+#[test]
+fn test_tags() {
+    let mut man = ProcessManager::new();
+    let worker: HashSet<String> = ["worker".to_string()].iter().cloned().collect();
+    for name in &["a", "b"] {
+        man.spawn_with_tags(
+            name.to_string(),
+            Command::new("sleep").arg("10"),
+            worker.clone(),
+        )
+        .expect("spawn_with_tags failed");
+    }
+    man.spawn("c".to_string(), Command::new("sleep").arg("10"))
+        .expect("spawn failed");
+
+    let mut workers = man.list_by_tag("worker");
+    workers.sort();
+    assert_eq!(workers, vec!["a", "b"]);
+    assert!(man.list_by_tag("db").is_empty());
+
+    let stopped = man.stop_by_tag("worker");
+    assert_eq!(stopped.len(), 2);
+    assert!(stopped.iter().all(|(_, result)| result.is_ok()));
+    assert_eq!(man.list(), vec!["c"]);
+    man.stop_all();
+}
+
+/// Chunks of output, each with the name of the process that wrote it.
+type NamedOutputs = Vec<(String, Vec<u8>)>;
+
+#[test]
+fn test_named_intercept() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn("foo".to_string(), Command::new("echo").arg("hello"))
+        .expect("spawn failed");
+    man.spawn("bar".to_string(), Command::new("echo").arg("world"))
+        .expect("spawn failed");
+
+    let outputs: Arc<RwLock<NamedOutputs>> = Default::default();
+    let inner_outputs = outputs.clone();
+    man.run_director_with_named_intercept(move |name, ev, k: &mut dyn FnMut(ProcessEvent)| {
+        if let ProcessEvent::Output(_handle, bytes) = &ev {
+            inner_outputs
+                .write()
+                .unwrap()
+                .push((name.to_string(), bytes.clone()));
+        }
+        k(ev)
+    })
+    .expect("run_director failed");
+
+    let mut outputs = outputs.read().unwrap().clone();
+    outputs.sort();
+    assert_eq!(
+        outputs,
+        vec![
+            ("bar".to_string(), b"world\n".to_vec()),
+            ("foo".to_string(), b"hello\n".to_vec())
+        ]
+    );
+}
+
+#[test]
+fn test_subscribe() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    let first = man.subscribe();
+    let second = man.subscribe();
+    drop(man.subscribe());
+    man.spawn("foo".to_string(), Command::new("echo").arg("hello"))
+        .expect("spawn failed");
+    man.run_director().expect("run_director failed");
+
+    for receiver in &[first, second] {
+        let events: Vec<(String, ProcessEvent)> = receiver.try_iter().collect();
+        assert!(events.iter().all(|(name, _ev)| name == "foo"));
+        assert!(events.iter().any(|(_name, ev)| matches!(
+            ev,
+            ProcessEvent::Output(HandleType::StdOutput, bytes) if bytes == b"hello\n"
+        )));
+        assert!(matches!(
+            events.last(),
+            Some((_name, ProcessEvent::Exited(_)))
+        ));
+    }
+}
+
+#[test]
+fn test_events_stream() {
+    use futures::StreamExt;
+
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn("foo".to_string(), Command::new("echo").arg("hello"))
+        .expect("spawn failed");
+
+    let events: Vec<(String, ProcessEvent)> =
+        futures::executor::block_on(man.events_stream().collect());
+    assert!(events.iter().any(|(name, ev)| name == "foo"
+        && matches!(
+            ev,
+            ProcessEvent::Output(HandleType::StdOutput, bytes) if bytes == b"hello\n"
+        )));
+    assert!(matches!(
+        events.last(),
+        Some((_name, ProcessEvent::Exited(_)))
+    ));
+}
+
+#[test]
+fn test_timed_intercept() {
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn("foo".to_string(), Command::new("echo").arg("hello"))
+        .expect("spawn failed");
+
+    let stamps: Arc<RwLock<Vec<std::time::SystemTime>>> = Default::default();
+    let inner_stamps = stamps.clone();
+    man.run_director_with_timed_intercept(move |_name, ev, k: &mut dyn FnMut(ProcessEvent)| {
+        if let ProcessEvent::Output(_handle, _bytes) = &ev.event {
+            inner_stamps.write().unwrap().push(ev.at);
+        }
+        k(ev.event)
+    })
+    .expect("run_director failed");
+
+    let now = std::time::SystemTime::now();
+    let stamps = stamps.read().unwrap();
+    assert_eq!(stamps.len(), 1);
+    assert!(now.duration_since(stamps[0]).unwrap() < Duration::from_secs(1));
 }
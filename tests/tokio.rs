@@ -0,0 +1,60 @@
+#![cfg(all(unix, feature = "tokio"))]
+
+use procman::*;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[tokio::test]
+async fn test_spawn_async() {
+    // The default poll interval is 200ms, which an asynchronous process does
+    // not have to wait for.
+    let man = ProcessManager::new();
+    let mut command = tokio::process::Command::new("echo");
+    command.arg("hello");
+
+    let start = Instant::now();
+    man.spawn_async("foo".to_string(), command)
+        .await
+        .expect("spawn_async failed");
+    assert!(start.elapsed() < Duration::from_millis(100));
+
+    let events = man.subscribe();
+    man.clone()
+        .with_poll_interval(Duration::from_millis(1))
+        .run_director()
+        .expect("run_director failed");
+    let events: Vec<ProcessEvent> = events.try_iter().map(|(_name, ev)| ev).collect();
+    assert!(events.iter().any(|ev| matches!(
+        ev,
+        ProcessEvent::Output(HandleType::StdOutput, bytes) if bytes == b"hello\n"
+    )));
+    match events.last() {
+        Some(ProcessEvent::Exited(status)) => assert!(status.success()),
+        other => panic!("expected Exited, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_spawn_async_latency() {
+    // With a poll interval far longer than the bound, only output that is
+    // awaited rather than polled for can arrive in time. The child stamps
+    // its output with the time it was written.
+    let man = ProcessManager::new().with_poll_interval(Duration::from_secs(10));
+    let mut command = tokio::process::Command::new("sh");
+    command.arg("-c").arg("sleep 0.1; date +%s%N");
+
+    let receive = async {
+        loop {
+            if let Some((_name, ProcessEvent::Output(HandleType::StdOutput, bytes))) =
+                man.try_recv_event()
+            {
+                let received = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+                let written: u64 = String::from_utf8(bytes).unwrap().trim().parse().unwrap();
+                return received - Duration::from_nanos(written);
+            }
+            tokio::task::yield_now().await;
+        }
+    };
+    let (spawned, latency) = futures::join!(man.spawn_async("foo".to_string(), command), receive);
+    spawned.expect("spawn_async failed");
+    assert!(latency < Duration::from_millis(1), "latency {:?}", latency);
+}
@@ -0,0 +1,142 @@
+#![cfg(all(unix, feature = "tracing"))]
+
+use procman::*;
+use std::fmt::Debug;
+use std::io::Result;
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+type SpanFields = Vec<(String, String)>;
+
+/// A subscriber that records the name and fields of each new span, as later
+/// updated, and the span each event was recorded under.
+#[derive(Clone, Default)]
+struct Recorder {
+    next_id: Arc<AtomicU64>,
+    spans: Arc<Mutex<Vec<(String, SpanFields)>>>,
+    events: Arc<Mutex<Vec<Option<u64>>>>,
+    current: Arc<Mutex<Vec<u64>>>,
+}
+
+struct Fields<'a>(&'a mut SpanFields);
+
+impl Visit for Fields<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0
+            .push((field.name().to_string(), format!("{:?}", value)));
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields = Vec::new();
+        span.record(&mut Fields(&mut fields));
+        self.spans
+            .lock()
+            .unwrap()
+            .push((span.metadata().name().to_string(), fields));
+        Id::from_u64(self.next_id.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut recorded = Vec::new();
+        values.record(&mut Fields(&mut recorded));
+        let mut spans = self.spans.lock().unwrap();
+        let fields = &mut spans[span.into_u64() as usize - 1].1;
+        for (name, value) in recorded {
+            match fields.iter_mut().find(|(field, _)| *field == name) {
+                Some(field) => field.1 = value,
+                None => fields.push((name, value)),
+            }
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {
+        let current = self.current.lock().unwrap().last().copied();
+        self.events.lock().unwrap().push(current);
+    }
+
+    fn enter(&self, span: &Id) {
+        self.current.lock().unwrap().push(span.into_u64());
+    }
+
+    fn exit(&self, _span: &Id) {
+        self.current.lock().unwrap().pop();
+    }
+}
+
+#[test]
+fn test_process_span() {
+    let recorder = Recorder::default();
+    let man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    let inner = man.clone();
+    let director = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        inner.run_director()
+    });
+
+    tracing::subscriber::with_default(recorder.clone(), || {
+        man.run_process_with_intercept(
+            "foo".to_string(),
+            Command::new("echo").arg("hello"),
+            |ev, k: &dyn Fn(ProcessEvent) -> Result<()>| k(ev),
+        )
+        .expect("run_process failed");
+    });
+    let statuses = director.join().unwrap().expect("run_director failed");
+    assert!(statuses["foo"].success());
+
+    let spans = recorder.spans.lock().unwrap();
+    assert_eq!(spans.len(), 1);
+    let (name, fields) = &spans[0];
+    assert_eq!(name, "process");
+    assert_eq!(fields[0], ("name".to_string(), "foo".to_string()));
+    assert_eq!(fields[1].0, "pid");
+    assert!(fields[1].1.parse::<u32>().is_ok());
+
+    // Output, two Eofs, and Exited, all within the span.
+    let events = recorder.events.lock().unwrap();
+    assert_eq!(*events, vec![Some(1); 4]);
+}
+
+#[test]
+fn test_replace_process_span() {
+    // The replacement is monitored on a thread of its own, so the recorder
+    // has to be the global default to see its span.
+    let recorder = Recorder::default();
+    tracing::subscriber::set_global_default(recorder.clone()).expect("set_global_default failed");
+    let mut man = ProcessManager::new().with_poll_interval(Duration::from_millis(1));
+    man.spawn("foo".to_string(), Command::new("sleep").arg("100"))
+        .expect("spawn failed");
+    man.replace_process(
+        "foo",
+        Command::new("sh")
+            .arg("-c")
+            .arg("echo READY; exec sleep 100"),
+        regex::Regex::new("^READY$").unwrap(),
+    )
+    .expect("replace_process failed");
+
+    // The replacement's span takes on the name it was swapped in under.
+    let pid = man.pid("foo").expect("pid failed").to_string();
+    let spans = recorder.spans.lock().unwrap().clone();
+    let (_name, fields) = spans
+        .iter()
+        .find(|(_name, fields)| fields.contains(&("pid".to_string(), pid.clone())))
+        .expect("no span for the replacement");
+    assert_eq!(fields[0], ("name".to_string(), "foo".to_string()));
+
+    man.stop_all();
+}
@@ -0,0 +1,44 @@
+//! Tests of what only Windows does differently.
+
+#![cfg(windows)]
+
+use procman::*;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_process_groups_windows() {
+    let mut man = ProcessManager::new()
+        .with_poll_interval(Duration::from_millis(1))
+        .with_handles(HandleSet::NONE)
+        .with_process_groups(true);
+    // The second ping starts long after the shell has been put in its job,
+    // which it then joins as well.
+    man.spawn(
+        "foo".to_string(),
+        Command::new("cmd").args([
+            "/C",
+            "ping -n 2 127.0.0.1 > nul & ping -n 100 127.0.0.1 > nul",
+        ]),
+    )
+    .expect("spawn failed");
+    thread::sleep(Duration::from_millis(1500));
+    assert!(running_pings() > 0);
+
+    man.stop_process("foo").expect("stop_process failed");
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(running_pings(), 0);
+}
+
+/// Count the ping processes that are running.
+fn running_pings() -> usize {
+    let output = Command::new("tasklist")
+        .args(["/FI", "IMAGENAME eq PING.EXE", "/FO", "CSV", "/NH"])
+        .output()
+        .expect("tasklist failed");
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| line.contains("PING.EXE"))
+        .count()
+}